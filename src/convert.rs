@@ -0,0 +1,71 @@
+use datafusion::arrow::compute::can_cast_types;
+use datafusion::arrow::datatypes::{DataType, TimeUnit};
+use pgrx::pg_sys::{self, Oid};
+
+use crate::utils::is_compatible;
+
+// Arrow type DataFusion casts to when the dataset type can't be read into the
+// Postgres column directly
+pub fn pg_to_arrow_type(type_oid: Oid) -> Option<DataType> {
+    match type_oid {
+        pg_sys::BOOLOID => Some(DataType::Boolean),
+        pg_sys::INT2OID => Some(DataType::Int16),
+        pg_sys::INT4OID => Some(DataType::Int32),
+        pg_sys::INT8OID => Some(DataType::Int64),
+        pg_sys::FLOAT4OID => Some(DataType::Float32),
+        pg_sys::FLOAT8OID => Some(DataType::Float64),
+        pg_sys::NUMERICOID => Some(DataType::Decimal128(38, 0)),
+        pg_sys::TEXTOID | pg_sys::VARCHAROID | pg_sys::BPCHAROID => Some(DataType::Utf8),
+        pg_sys::DATEOID => Some(DataType::Date32),
+        pg_sys::TIMESTAMPOID => Some(DataType::Timestamp(TimeUnit::Microsecond, None)),
+        _ => None,
+    }
+}
+
+fn is_integer(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64
+            | DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64
+    )
+}
+
+// whether every value of one type is represented exactly in another
+pub fn is_lossless_cast(from: &DataType, to: &DataType) -> bool {
+    use DataType::*;
+
+    match (from, to) {
+        (Int8, Int16 | Int32 | Int64) => true,
+        (Int16, Int32 | Int64) => true,
+        (Int32, Int64) => true,
+        (UInt8, Int16 | Int32 | Int64) => true,
+        (UInt16, Int32 | Int64) => true,
+        (UInt32, Int64) => true,
+        // integers of up to 24 bits fit the float4 mantissa, up to 53 bits the float8 one
+        (Int8 | Int16 | UInt8 | UInt16, Float32) => true,
+        (Int8 | Int16 | Int32 | UInt8 | UInt16 | UInt32, Float64) => true,
+        (Float16, Float32 | Float64) => true,
+        (Float32, Float64) => true,
+        // the widest integer has 20 digits
+        (f, Decimal128(_, 0)) if is_integer(f) => true,
+        (f, Utf8) if is_integer(f) => true,
+        (Decimal128(_, _) | Decimal256(_, _) | Boolean, Utf8) => true,
+        (Date32 | Date64, Timestamp(TimeUnit::Microsecond, None)) => true,
+        _ => false,
+    }
+}
+
+// decide how a dataset column is read into a Postgres column: Ok(None) when it
+// converts directly, Ok(Some(t)) when DataFusion has to cast it to t first
+pub fn coerce_column(data_type: &DataType, type_oid: Oid) -> Result<Option<DataType>, String> {
+    if is_compatible(data_type, type_oid) {
+        return Ok(None);
+    }
+
+    match pg_to_arrow_type(type_oid) {
+        Some(target) if is_lossless_cast(data_type, &target) && can_cast_types(data_type, &target) => {
+            Ok(Some(target))
+        }
+        _ => Err(format!("Arrow type {data_type} cannot be converted without loss")),
+    }
+}
@@ -1,17 +1,35 @@
-use std::os::raw::c_int;
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_int};
 use std::ptr;
 use std::ptr::addr_of_mut;
+use std::str::FromStr;
 
 
 use async_std::task;
+use datafusion::common::DFSchema;
+use datafusion::dataframe::DataFrame;
+use datafusion::logical_expr::Expr;
 use pgrx::memcxt::PgMemoryContexts;
 use pgrx::pg_sys::{AsPgCStr, Datum, TopMemoryContext};
 use pgrx::PgTupleDesc;
 use pgrx::prelude::*;
 
-use crate::utils::{generate_test_data_for_oid, run_df_sql, run_df_sql_local, SerdeList, extract_target_columns};
+use crate::utils::{generate_test_data_for_oid, run_df_query, SerdeList, extract_target_columns, TargetColumn, arrow_to_pg_type, check_target_columns, table_schema};
+use crate::pushdown::{classify_conditions, deparse_expr, join_conditions, param_value, DeparseContext};
+use crate::options::{options_to_hashmap, DataFormat, ServerOptions, TableOptions, UserMappingOptions};
+use crate::server::{create_session_context, infer_schema, list_datasets};
 use crate::results::DFResult;
 
+// cost of setting up the DataFusion session and query, once per scan
+const DATAFUSION_STARTUP_COST: f64 = 100.0;
+
+// every execution, rescans included, reads the rows of the unparameterized
+// scan, parameters only cut down the rows returned
+unsafe fn scan_cost(baserel: *mut pg_sys::RelOptInfo, rows: f64) -> f64 {
+    DATAFUSION_STARTUP_COST + (*baserel).rows * pg_sys::cpu_operator_cost + rows * pg_sys::cpu_tuple_cost
+}
+
 #[pg_guard]
 pub extern "C" fn datafusion_get_foreign_rel_size(
     root: *mut pg_sys::PlannerInfo,
@@ -42,7 +60,27 @@ pub extern "C" fn datafusion_get_foreign_rel_size(
 
         let ctx = my_fdw_state.self_ctx.value();
 
-        my_fdw_state.target_cols = extract_target_columns(root, baserel, foreigntableid);
+        let table_options = TableOptions::from_foreign_table(foreigntableid);
+        let schema = table_schema(&table_options);
+
+        // where conditions DataFusion can evaluate
+        let params = RefCell::new(vec![]);
+        let deparse_ctx = DeparseContext {
+            relid: (*baserel).relid as u32,
+            foreigntableid,
+            schema: &schema,
+            case_test: None,
+            params: &params,
+        };
+        let (remote_filters, remote_conds, local_conds) =
+            classify_conditions(&deparse_ctx, (*baserel).baserestrictinfo);
+        my_fdw_state.remote_filters = remote_filters;
+        my_fdw_state.remote_conds = remote_conds;
+        my_fdw_state.remote_params = params.into_inner();
+
+        my_fdw_state.target_cols = extract_target_columns(root, baserel, foreigntableid, &local_conds);
+        check_target_columns(foreigntableid, &table_options, &schema, &my_fdw_state.target_cols);
+        my_fdw_state.schema = Some(schema);
 
 
         (*baserel).fdw_private = PgMemoryContexts::For(ctx).leak_and_drop_on_delete(my_fdw_state) as _;
@@ -57,7 +95,7 @@ pub extern "C" fn datafusion_get_foreign_rel_size(
 pub extern "C" fn datafusion_get_foreign_paths(
     root: *mut pg_sys::PlannerInfo,
     baserel: *mut pg_sys::RelOptInfo,
-    _foreigntableid: pg_sys::Oid,
+    foreigntableid: pg_sys::Oid,
 ) {
     debug2!("---> get_foreign_paths");
     unsafe {
@@ -67,14 +105,60 @@ pub extern "C" fn datafusion_get_foreign_paths(
             baserel,
             ptr::null_mut(), // default pathtarget
             (*baserel).rows,
-            0.0,
-            0.0,
+            DATAFUSION_STARTUP_COST,
+            scan_cost(baserel, (*baserel).rows),
             ptr::null_mut(), // no pathkeys
-            ptr::null_mut(), // no outer rel either
+            (*baserel).lateral_relids,
             ptr::null_mut(), // no extra plan
             ptr::null_mut(), // no fdw_private data
         );
         pg_sys::add_path(baserel, &mut ((*path).path));
+
+        // parameterized paths for join clauses DataFusion can evaluate with
+        // the values of the outer relations, e.g. a lookup by key per row of a
+        // nested loop
+        let state = PgBox::<DataFusionFdwStat>::from_pg((*baserel).fdw_private as _);
+        let params = RefCell::new(vec![]);
+        let deparse_ctx = DeparseContext {
+            relid: (*baserel).relid as u32,
+            foreigntableid,
+            schema: state.schema.as_ref().unwrap(),
+            case_test: None,
+            params: &params,
+        };
+
+        let mut param_infos = vec![];
+        for rinfo in join_conditions(root, baserel) {
+            if deparse_expr(&deparse_ctx, (*rinfo).clause as *mut pg_sys::Node).is_none() {
+                continue;
+            }
+
+            let outer_relids = pg_sys::bms_difference((*rinfo).clause_relids, (*baserel).relids);
+            if pg_sys::bms_num_members(outer_relids) == 0 {
+                continue;
+            }
+            let required_outer = pg_sys::bms_union(outer_relids, (*baserel).lateral_relids);
+
+            let param_info = pg_sys::get_baserel_parampathinfo(root, baserel, required_outer);
+            if param_infos.contains(&param_info) {
+                continue;
+            }
+            param_infos.push(param_info);
+
+            let path = pg_sys::create_foreignscan_path(
+                root,
+                baserel,
+                ptr::null_mut(),
+                (*param_info).ppi_rows,
+                DATAFUSION_STARTUP_COST,
+                scan_cost(baserel, (*param_info).ppi_rows),
+                ptr::null_mut(),
+                required_outer,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            );
+            pg_sys::add_path(baserel, &mut ((*path).path));
+        }
     }
 }
 
@@ -82,7 +166,7 @@ pub extern "C" fn datafusion_get_foreign_paths(
 pub extern "C" fn datafusion_get_foreign_plan(
     _root: *mut pgrx::prelude::pg_sys::PlannerInfo,
     baserel: *mut pgrx::prelude::pg_sys::RelOptInfo,
-    _foreigntableid: pgrx::prelude::pg_sys::Oid,
+    foreigntableid: pgrx::prelude::pg_sys::Oid,
     _best_path: *mut pgrx::prelude::pg_sys::ForeignPath,
     tlist: *mut pgrx::prelude::pg_sys::List,
     scan_clauses: *mut pgrx::prelude::pg_sys::List,
@@ -91,9 +175,43 @@ pub extern "C" fn datafusion_get_foreign_plan(
     debug2!("---> get_foreign_plan");
 
     unsafe {
-        let state = PgBox::<DataFusionFdwStat>::from_pg((*baserel).fdw_private as _);
+        let mut state = PgBox::<DataFusionFdwStat>::from_pg((*baserel).fdw_private as _);
+
+        // join clauses of a parameterized path are pushed down too, the
+        // outer columns they reference become parameters
+        let params = RefCell::new(std::mem::take(&mut state.remote_params));
+        let schema = state.schema.take().unwrap();
+        let deparse_ctx = DeparseContext {
+            relid: (*baserel).relid as u32,
+            foreigntableid,
+            schema: &schema,
+            case_test: None,
+            params: &params,
+        };
 
-        let scan_clauses = pg_sys::extract_actual_clauses(scan_clauses, false);
+        // conditions pushed down to DataFusion are not rechecked locally
+        let mut local_clauses = PgList::<pg_sys::RestrictInfo>::new();
+        for rinfo in PgList::<pg_sys::RestrictInfo>::from_pg(scan_clauses).iter_ptr() {
+            if state.remote_conds.contains(&rinfo) {
+                continue;
+            }
+            if !(*rinfo).pseudoconstant {
+                if let Some(expr) = deparse_expr(&deparse_ctx, (*rinfo).clause as *mut pg_sys::Node) {
+                    state.remote_filters.push(expr);
+                    continue;
+                }
+            }
+            local_clauses.push(rinfo);
+        }
+        let scan_clauses = pg_sys::extract_actual_clauses(local_clauses.into_pg(), false);
+
+        // evaluated at execution, outer columns are replaced by executor
+        // parameters once the plan is built
+        let mut fdw_exprs = PgList::<pg_sys::Node>::new();
+        for param in params.into_inner() {
+            fdw_exprs.push(param);
+        }
+        state.schema = Some(schema);
 
         let ctx = PgMemoryContexts::For(state.self_ctx.value());
 
@@ -103,7 +221,7 @@ pub extern "C" fn datafusion_get_foreign_plan(
             tlist,
             scan_clauses,
             (*baserel).relid,
-            ptr::null_mut(),
+            fdw_exprs.into_pg(),
             fdw_private,
             ptr::null_mut(),
             ptr::null_mut(),
@@ -113,15 +231,21 @@ pub extern "C" fn datafusion_get_foreign_plan(
 }
 
 
+// planning state, the plan keeps it and it is read-only at execution since a
+// cached plan can be executed several times
 struct DataFusionFdwStat {
     pub self_ctx: PgMemoryContexts,
     pub current: u64,
     pub total: u64,
     // query conditions
-    pub target_cols: Vec<String>,
-    
-
-    pub df_result: Option<DFResult>,
+    pub target_cols: Vec<TargetColumn>,
+    // where conditions evaluated by DataFusion, and the planner's
+    // RestrictInfos they come from (valid during planning only)
+    pub remote_filters: Vec<Expr>,
+    pub remote_conds: Vec<*mut pg_sys::RestrictInfo>,
+    // expressions the placeholders of remote_filters stand for while planning
+    pub remote_params: Vec<*mut pg_sys::Node>,
+    pub schema: Option<DFSchema>,
 }
 
 impl SerdeList for DataFusionFdwStat {}
@@ -132,12 +256,53 @@ impl DataFusionFdwStat {
             current: 0,
             total: 0,
             target_cols: Vec::new(),
+            remote_filters: Vec::new(),
+            remote_conds: Vec::new(),
+            remote_params: Vec::new(),
+            schema: None,
             self_ctx,
-            df_result: None,
         }
     }
 }
 
+// state of one execution of the scan, allocated in the query memory context
+struct DataFusionScanState {
+    target_cols: Vec<TargetColumn>,
+    table_options: TableOptions,
+    remote_filters: Vec<Expr>,
+    // executor state and types of the expressions the placeholders stand for
+    param_exprs: Vec<*mut pg_sys::ExprState>,
+    param_types: Vec<pg_sys::Oid>,
+    // remote query, built once and run with the current parameters on
+    // every (re)scan
+    df: Option<DataFrame>,
+    df_result: Option<DFResult>,
+}
+
+impl DataFusionScanState {
+    fn remote_columns(&self) -> String {
+        self.target_cols
+            .iter()
+            .map(|c| format!("\"{}\"", c.name))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    fn remote_where(&self) -> String {
+        if self.remote_filters.is_empty() {
+            return String::new();
+        }
+
+        let filters = self
+            .remote_filters
+            .iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>()
+            .join(" AND ");
+        format!(" where {filters}")
+    }
+}
+
 #[pg_guard]
 pub extern "C" fn datafusion_begin_foreign_scan(
     node: *mut pg_sys::ForeignScanState,
@@ -148,45 +313,114 @@ pub extern "C" fn datafusion_begin_foreign_scan(
     unsafe {
         let scan_state = (*node).ss;
         let plan = scan_state.ps.plan as *mut pg_sys::ForeignScan;
-        let mut state = DataFusionFdwStat::deserialize_from_list((*plan).fdw_private as _);
-        assert!(!state.is_null());
+        let plan_state = DataFusionFdwStat::deserialize_from_list((*plan).fdw_private as _);
+        assert!(!plan_state.is_null());
 
+        let relid = (*scan_state.ss_currentRelation).rd_id;
+        let table_options = TableOptions::from_foreign_table(relid);
 
-        // deparse, where, join, sort and limit
-        // run remote query
+        let fdw_exprs = (*plan).fdw_exprs;
+        let param_exprs = PgList::<pg_sys::ExprState>::from_pg(
+            pg_sys::ExecInitExprList(fdw_exprs, node as *mut pg_sys::PlanState),
+        )
+        .iter_ptr()
+        .collect();
+        let param_types = PgList::<pg_sys::Node>::from_pg(fdw_exprs)
+            .iter_ptr()
+            .map(|param| pg_sys::exprType(param))
+            .collect();
+
+        let mut state = DataFusionScanState {
+            target_cols: plan_state.target_cols.clone(),
+            table_options,
+            remote_filters: plan_state.remote_filters.clone(),
+            param_exprs,
+            param_types,
+            df: None,
+            df_result: None,
+        };
 
         if eflags & pg_sys::EXEC_FLAG_EXPLAIN_ONLY as c_int > 0 {
             debug2!("explain only, do nothing");
         } else {
-
-            let sql_str = format!(r#"SELECT "{}" from hits;"#, state.target_cols.join(","));
-            debug2!("{sql_str}");
-            state.df_result = match run_df_sql_local(sql_str.as_str()) {
-                Ok(v) => match (task::block_on(v.collect())) {
-                    Ok(v) => Some(DFResult::new(v)),
-                    _ => None
-                }
-                _ => None
+            debug2!("select {} from {}{}", state.remote_columns(), state.table_options, state.remote_where());
+            state.df = match run_df_query(&state.table_options, &state.target_cols, &state.remote_filters) {
+                Ok(v) => Some(v),
+                Err(e) => error!("datafusion query failed: {e}"),
             };
         }
 
-        (*node).fdw_state = state.into_pg() as _;
+        let estate = scan_state.ps.state;
+        (*node).fdw_state =
+            PgMemoryContexts::For((*estate).es_query_cxt).leak_and_drop_on_delete(state) as _;
     }
 }
 
+// run the remote query with the current values of its parameters
+unsafe fn run_query(node: *mut pg_sys::ForeignScanState, state: &mut DataFusionScanState) {
+    let econtext = (*node).ss.ps.ps_ExprContext;
+    let params = PgMemoryContexts::For((*econtext).ecxt_per_tuple_memory).switch_to(|_| {
+        state
+            .param_exprs
+            .iter()
+            .zip(&state.param_types)
+            .map(|(expr_state, type_oid)| {
+                let mut is_null = false;
+                let evalfunc = (**expr_state).evalfunc.unwrap();
+                let datum = evalfunc(*expr_state, econtext, &mut is_null);
+                param_value(datum, is_null, *type_oid)
+            })
+            .collect::<Vec<_>>()
+    });
+
+    // drop the rows of the previous scan before fetching the next ones
+    state.df_result = None;
+    let df = state.df.clone().unwrap();
+    let df = if params.is_empty() {
+        Ok(df)
+    } else {
+        df.with_param_values(params)
+    };
+    state.df_result = match df {
+        Ok(v) => match (task::block_on(v.collect())) {
+            Ok(v) => Some(DFResult::new(v, state.target_cols.iter().map(|c| (c.type_oid, c.typmod)).collect())),
+            Err(e) => error!("datafusion query failed: {e}"),
+        }
+        Err(e) => error!("datafusion query failed: {e}"),
+    };
+}
+
 #[pg_guard]
-pub extern "C" fn datafusion_re_scan_foreign_scan(_node: *mut pg_sys::ForeignScanState) {
+pub extern "C" fn datafusion_re_scan_foreign_scan(node: *mut pg_sys::ForeignScanState) {
     debug2!("---> re_scan_foreign_scan");
+    unsafe {
+        // the parameters may have changed, e.g. for the next outer row of a
+        // nested loop, the query then runs again on the next iteration.
+        // Otherwise the rows already fetched are returned once more
+        let mut state = PgBox::<DataFusionScanState>::from_pg((*node).fdw_state as _);
+        if (*node).ss.ps.chgParam.is_null() {
+            if let Some(df_result) = state.df_result.as_mut() {
+                df_result.rewind();
+            }
+        } else {
+            state.df_result = None;
+        }
+    }
 }
 
 #[pg_guard]
-pub extern "C" fn datafusion_end_foreign_scan(_node: *mut pg_sys::ForeignScanState) {
+pub extern "C" fn datafusion_end_foreign_scan(node: *mut pg_sys::ForeignScanState) {
     debug2!("---> end_foreign_scan");
-    // unsafe {
-    // let mut my_fdw_stat =
-    //     PgBox::<RandomFdwStat>::from_pg((*node).fdw_state as *mut RandomFdwStat);
-    // // debug2!("{:?}", my_fdw_stat.opts);
-    // }
+    unsafe {
+        // the state itself goes with the query memory context, release the
+        // fetched rows now
+        let mut state = PgBox::<DataFusionScanState>::from_pg((*node).fdw_state as _);
+        if state.is_null() {
+            return;
+        }
+        state.df_result = None;
+        state.df = None;
+    }
 }
 
 #[pg_guard]
@@ -195,7 +429,7 @@ pub extern "C" fn datafusion_iterate_foreign_scan(
 ) -> *mut pg_sys::TupleTableSlot {
     debug2!("---> iterate_foreign_scan");
     unsafe {
-        let mut state = PgBox::<DataFusionFdwStat>::from_pg((*node).fdw_state as _);
+        let mut state = PgBox::<DataFusionScanState>::from_pg((*node).fdw_state as _);
         let slot = (*node).ss.ss_ScanTupleSlot;
 
         // clear slot
@@ -203,8 +437,11 @@ pub extern "C" fn datafusion_iterate_foreign_scan(
             clear(slot);
         }
 
+        // the parameters are only set by the executor once the scan is
+        // started, so the query runs on the first call rather than in
+        // BeginForeignScan
         if state.df_result.is_none() {
-            return slot;
+            run_query(node, &mut state);
         };
 
         let mut value_iter = match state.df_result.as_mut().unwrap().next_record() {
@@ -217,13 +454,25 @@ pub extern "C" fn datafusion_iterate_foreign_scan(
 
         let tuple_desc = PgTupleDesc::from_pg_copy(tup_desc);
 
-        for (col_index, attr) in tuple_desc.iter().enumerate() {
+        // columns not referenced by the query are not fetched
+        for col_index in 0..tuple_desc.len() {
+            *(*slot).tts_isnull.add(col_index) = true;
+        }
+
+        for col in state.target_cols.iter() {
+            let col_index = (col.attnum - 1) as usize;
             let tts_isnull = (*slot).tts_isnull.add(col_index);
             let tts_value = (*slot).tts_values.add(col_index);
-            // todo: check datatype
-            // check null
-            *tts_value = value_iter.next().unwrap(); // todo: use Option<Datum>
-            *tts_isnull = false;
+            match value_iter.next().unwrap() {
+                Some(v) => {
+                    *tts_value = v;
+                    *tts_isnull = false;
+                }
+                None => {
+                    *tts_value = Datum::from(0usize);
+                    *tts_isnull = true;
+                }
+            }
         }
 
         pgrx::prelude::pg_sys::ExecStoreVirtualTuple(slot);
@@ -239,7 +488,7 @@ pub(super) extern "C" fn datafusion_explain_foreign_scan(
 ) {
     debug2!("---> explain_foreign_scan");
     unsafe {
-        let mut state = PgBox::<DataFusionFdwStat>::from_pg((*node).fdw_state as _);
+        let state = PgBox::<DataFusionScanState>::from_pg((*node).fdw_state as _);
         if state.is_null() {
             return;
         }
@@ -248,14 +497,123 @@ pub(super) extern "C" fn datafusion_explain_foreign_scan(
 
         let label = ctx.pstrdup("Remote Query");
 
-        let value = ctx.pstrdup(&format!("select {} from datafusion",
-        state.target_cols.join(",")));
+        let value = ctx.pstrdup(&format!("select {} from {}{}",
+        state.remote_columns(),
+        state.table_options,
+        state.remote_where()));
         pg_sys::ExplainPropertyText(label, value, es);
 
     }
 }
 
 
+unsafe fn quote_identifier(ident: &str) -> String {
+    let quoted = pg_sys::quote_identifier(ident.as_pg_cstr());
+    CStr::from_ptr(quoted).to_str().unwrap().to_owned()
+}
+
+unsafe fn quote_literal(literal: &str) -> String {
+    let quoted = pg_sys::quote_literal_cstr(literal.as_pg_cstr());
+    CStr::from_ptr(quoted).to_str().unwrap().to_owned()
+}
+
+#[pg_guard]
+pub extern "C" fn datafusion_import_foreign_schema(
+    stmt: *mut pg_sys::ImportForeignSchemaStmt,
+    server_oid: pg_sys::Oid,
+) -> *mut pg_sys::List {
+    debug2!("---> import_foreign_schema");
+
+    unsafe {
+        // the remote schema is the location holding the datasets
+        let location = CStr::from_ptr((*stmt).remote_schema).to_str().unwrap().to_owned();
+
+        let mut import_options = options_to_hashmap((*stmt).options);
+        let format = match import_options.remove("format") {
+            Some(v) => DataFormat::from_str(&v).unwrap_or_else(|e| error!("{e}")),
+            None => DataFormat::Parquet,
+        };
+        if let Some(key) = import_options.keys().next() {
+            error!("invalid option \"{key}\" for IMPORT FOREIGN SCHEMA, valid options are: format");
+        }
+
+        let server = pg_sys::GetForeignServer(server_oid);
+        let server_name = CStr::from_ptr((*server).servername).to_str().unwrap().to_owned();
+
+        let server_options = ServerOptions::from_foreign_server(server_oid);
+        let user_options = UserMappingOptions::for_server(server_oid, &server_options);
+        let ctx = create_session_context(&server_options, &user_options, &[])
+            .unwrap_or_else(|e| error!("{e}"));
+
+        let datasets = list_datasets(&ctx, &location, format).unwrap_or_else(|e| error!("{e}"));
+
+        // LIMIT TO and EXCEPT name the local tables, compare them to the dataset names
+        let table_list: PgList<pg_sys::RangeVar> = PgList::from_pg((*stmt).table_list);
+        let listed = table_list
+            .iter_ptr()
+            .map(|rv| CStr::from_ptr((*rv).relname).to_str().unwrap().to_owned())
+            .collect::<Vec<_>>();
+
+        let mut ret = PgList::<c_char>::new();
+
+        for dataset in datasets {
+            let table_name = dataset.name.to_lowercase();
+
+            let is_listed = listed.contains(&table_name);
+            let wanted = match (*stmt).list_type {
+                pg_sys::ImportForeignSchemaType_FDW_IMPORT_SCHEMA_LIMIT_TO => is_listed,
+                pg_sys::ImportForeignSchemaType_FDW_IMPORT_SCHEMA_EXCEPT => !is_listed,
+                _ => true,
+            };
+            if !wanted {
+                continue;
+            }
+
+            let schema = infer_schema(&ctx, &dataset.location, format)
+                .unwrap_or_else(|e| error!("cannot infer schema of \"{}\": {e}", dataset.location));
+
+            let mut columns = vec![];
+            for field in schema.fields() {
+                let Some(pg_type) = arrow_to_pg_type(field.data_type()) else {
+                    notice!(
+                        "skipping column \"{}\" of \"{}\": unsupported type {}",
+                        field.name(),
+                        dataset.name,
+                        field.data_type()
+                    );
+                    continue;
+                };
+
+                // Postgres folds identifiers to lower case, keep the Arrow name in column_name
+                let column_name = field.name().to_lowercase();
+                let mut column = format!("{} {}", quote_identifier(&column_name), pg_type);
+                if column_name != *field.name() {
+                    column.push_str(&format!(" OPTIONS (column_name {})", quote_literal(field.name())));
+                }
+                if !field.is_nullable() {
+                    column.push_str(" NOT NULL");
+                }
+                columns.push(column);
+            }
+
+            let sql = format!(
+                "CREATE FOREIGN TABLE {} ({}) SERVER {} OPTIONS (location {}, format {}, table_name {})",
+                quote_identifier(&table_name),
+                columns.join(", "),
+                quote_identifier(&server_name),
+                quote_literal(&dataset.location),
+                quote_literal(format.name()),
+                quote_literal(&dataset.name),
+            );
+            debug2!("{sql}");
+
+            ret.push(sql.as_pg_cstr());
+        }
+
+        ret.into_pg()
+    }
+}
+
 pub static mut DATAFUSION_FDW_ROUTINE: pg_sys::FdwRoutine = pg_sys::FdwRoutine {
     type_: pg_sys::NodeTag::T_FdwRoutine,
     BeginForeignScan: Some(datafusion_begin_foreign_scan),
@@ -291,7 +649,7 @@ pub static mut DATAFUSION_FDW_ROUTINE: pg_sys::FdwRoutine = pg_sys::FdwRoutine {
     ExplainForeignModify: None,
     ExplainDirectModify: None,
     AnalyzeForeignTable: None,
-    ImportForeignSchema: None,
+    ImportForeignSchema: Some(datafusion_import_foreign_schema),
     #[cfg(any(feature = "pg15", feature = "pg16"))]
     ExecForeignTruncate: None,
     IsForeignScanParallelSafe: None,
@@ -0,0 +1,49 @@
+use pgrx::{GucContext, GucFlags, GucRegistry, GucSetting};
+
+// DataFusion session settings, tunable per session or per role
+pub static TARGET_PARTITIONS: GucSetting<i32> = GucSetting::<i32>::new(8);
+pub static BATCH_SIZE: GucSetting<i32> = GucSetting::<i32>::new(6666);
+pub static PARQUET_PRUNING: GucSetting<bool> = GucSetting::<bool>::new(true);
+pub static PARQUET_BLOOM_FILTER_PRUNING: GucSetting<bool> = GucSetting::<bool>::new(true);
+
+pub fn init() {
+    GucRegistry::define_int_guc(
+        "datafusion_fdw.target_partitions",
+        "Number of partitions DataFusion uses to execute a query.",
+        "Higher values read more files and row groups in parallel.",
+        &TARGET_PARTITIONS,
+        1,
+        1024,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_int_guc(
+        "datafusion_fdw.batch_size",
+        "Number of rows in each record batch produced by DataFusion.",
+        "",
+        &BATCH_SIZE,
+        1,
+        i32::MAX,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_bool_guc(
+        "datafusion_fdw.parquet_pruning",
+        "Skip parquet row groups using their min/max statistics.",
+        "",
+        &PARQUET_PRUNING,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+
+    GucRegistry::define_bool_guc(
+        "datafusion_fdw.parquet_bloom_filter_pruning",
+        "Skip parquet row groups using their bloom filters.",
+        "",
+        &PARQUET_BLOOM_FILTER_PRUNING,
+        GucContext::Userset,
+        GucFlags::default(),
+    );
+}
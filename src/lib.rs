@@ -1,12 +1,22 @@
 use pgrx::prelude::*;
 
+mod convert;
 mod functions;
+mod guc;
+mod options;
+mod pushdown;
 mod utils;
 mod results;
 mod server;
 
 pgrx::pg_module_magic!();
 
+#[allow(non_snake_case)]
+#[pg_guard]
+pub extern "C" fn _PG_init() {
+    guc::init();
+}
+
 extension_sql!(
     r#"
 CREATE FUNCTION datafusion_fdw_handler()
@@ -24,12 +34,921 @@ fn hello_datafusion_fdw() -> &'static str {
 #[cfg(any(test, feature = "pg_test"))]
 #[pg_schema]
 mod tests {
+    use std::sync::Arc;
+
+    use datafusion::arrow::array::*;
+    use datafusion::arrow::compute::cast;
+    use datafusion::arrow::datatypes::*;
+    use datafusion::arrow::ipc::writer::FileWriter;
     use pgrx::prelude::*;
 
     #[pg_test]
     fn test_hello_datafusion_fdw() {
         assert_eq!("Hello, datafusion_fdw", crate::hello_datafusion_fdw());
     }
+
+    #[pg_test]
+    fn test_guc_defaults() {
+        let batch_size = Spi::get_one::<String>("SELECT current_setting('datafusion_fdw.batch_size')");
+        assert_eq!(Ok(Some(String::from("6666"))), batch_size);
+    }
+
+    #[pg_test]
+    fn test_decimal_to_string() {
+        use crate::utils::decimal_to_string;
+
+        assert_eq!("123.45", decimal_to_string(String::from("12345"), 2));
+        assert_eq!("-0.05", decimal_to_string(String::from("-5"), 2));
+        assert_eq!("1200", decimal_to_string(String::from("12"), -2));
+    }
+
+    // write lines of CSV, header first, as the only file of a fresh directory
+    fn write_csv(dir: &str, lines: &[&str]) {
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(format!("{dir}/data.csv"), lines.join("\n") + "\n").unwrap();
+    }
+
+    fn col(name: &str, array: impl Array + 'static) -> (&str, ArrayRef) {
+        (name, Arc::new(array))
+    }
+
+    // write a record batch as the only Arrow IPC file of a fresh directory
+    fn write_arrow(dir: &str, columns: Vec<(&str, ArrayRef)>) {
+        let batch = RecordBatch::try_from_iter(columns).unwrap();
+
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        let file = std::fs::File::create(format!("{dir}/data.arrow")).unwrap();
+        let mut writer = FileWriter::try_new(file, &batch.schema()).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
+    }
+
+    fn create_foreign_table(name: &str, columns: &str, dir: &str, format: &str) {
+        Spi::run(&format!(
+            "CREATE FOREIGN TABLE {name} ({columns}) SERVER df_server
+                OPTIONS (location '{dir}/', format '{format}')"
+        ))
+        .unwrap();
+    }
+
+    fn create_server(name: &str, options: &str) {
+        Spi::run(
+            "CREATE FOREIGN DATA WRAPPER datafusion_wrapper
+                HANDLER datafusion_fdw_handler VALIDATOR datafusion_fdw_validator",
+        )
+        .unwrap();
+        Spi::run(&format!("CREATE SERVER {name} FOREIGN DATA WRAPPER datafusion_wrapper {options}")).unwrap();
+    }
+
+    #[pg_test]
+    fn test_table_resolves_under_catalog_root() {
+        let root = "/tmp/datafusion_fdw_test/catalog";
+        write_csv(&format!("{root}/sales/orders"), &["id,name", "1,first"]);
+        create_server("df_server", &format!("OPTIONS (catalog_root '{root}')"));
+        Spi::run(
+            "CREATE FOREIGN TABLE orders (id bigint, name text) SERVER df_server
+                OPTIONS (schema_name 'sales', format 'csv')",
+        )
+        .unwrap();
+
+        let name = Spi::get_one::<String>("SELECT name FROM orders WHERE id = 1");
+        assert_eq!(Ok(Some(String::from("first"))), name);
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "invalid value for option \"table_name\": \"../../etc\"")]
+    fn test_table_name_cannot_leave_catalog_root() {
+        create_server("df_server", "OPTIONS (catalog_root '/tmp/datafusion_fdw_test/catalog')");
+        Spi::run(
+            "CREATE FOREIGN TABLE passwd (line text) SERVER df_server
+                OPTIONS (table_name '../../etc', format 'csv')",
+        )
+        .unwrap();
+    }
+
+    #[pg_test]
+    fn test_whole_row_and_placeholder_columns() {
+        let dir = "/tmp/datafusion_fdw_test/whole_row";
+        write_csv(dir, &["id,name", "1,first"]);
+        create_server("df_server", "");
+        Spi::run(&format!(
+            "CREATE FOREIGN TABLE ft (id bigint, name text) SERVER df_server
+                OPTIONS (location '{dir}/', format 'csv')"
+        ))
+        .unwrap();
+
+        let row = Spi::get_one::<String>("SELECT ft::text FROM ft");
+        assert_eq!(Ok(Some(String::from("(1,first)"))), row);
+
+        // pv is a PlaceHolderVar computed at the scan of ft
+        let names = Spi::get_one::<String>(
+            "SELECT string_agg(coalesce(pv, '-'), ',' ORDER BY x)
+                FROM (VALUES (1), (2)) t(x)
+                LEFT JOIN (SELECT id, coalesce(name, 'none') AS pv FROM ft) s ON s.id = t.x",
+        );
+        assert_eq!(Ok(Some(String::from("first,-"))), names);
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "user mapping not found")]
+    fn test_bucket_requires_user_mapping() {
+        create_server("df_server", "OPTIONS (bucket 'data1')");
+        Spi::run("CREATE FOREIGN TABLE ft (id bigint) SERVER df_server OPTIONS (location 's3://data1/ft/')")
+            .unwrap();
+        Spi::run("SELECT * FROM ft").unwrap();
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "only superuser may set the option \"use_environment_credentials\"")]
+    fn test_environment_credentials_are_superuser_only() {
+        create_server("df_server", "");
+        Spi::run("CREATE ROLE df_user").unwrap();
+        Spi::run("GRANT USAGE ON FOREIGN DATA WRAPPER datafusion_wrapper TO df_user").unwrap();
+        Spi::run("SET LOCAL ROLE df_user").unwrap();
+
+        Spi::run(
+            "CREATE SERVER df_env FOREIGN DATA WRAPPER datafusion_wrapper
+                OPTIONS (bucket 'data1', use_environment_credentials 'true')",
+        )
+        .unwrap();
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "column \"nn\" does not exist in foreign table \"ft\"")]
+    fn test_misspelled_column_is_an_error() {
+        let dir = "/tmp/datafusion_fdw_test/misspelled";
+        write_arrow(dir, vec![col("n", Int64Array::from(vec![1]))]);
+        create_server("df_server", "");
+        create_foreign_table("ft", "nn bigint", dir, "arrow");
+
+        Spi::run("SELECT nn FROM ft").unwrap();
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "column \"b\" of type Boolean cannot be read into Postgres type date")]
+    fn test_incompatible_column_type_is_an_error() {
+        let dir = "/tmp/datafusion_fdw_test/incompatible";
+        write_arrow(dir, vec![col("b", BooleanArray::from(vec![true]))]);
+        create_server("df_server", "");
+        create_foreign_table("ft", "b date", dir, "arrow");
+
+        Spi::run("SELECT b FROM ft").unwrap();
+    }
+
+    #[pg_test]
+    fn test_timestamps_follow_session_time_zone() {
+        let dir = "/tmp/datafusion_fdw_test/timestamps";
+        write_arrow(
+            dir,
+            vec![
+                col("zoned1", TimestampMicrosecondArray::from(vec![0]).with_timezone("UTC")),
+                col("zoned2", TimestampMicrosecondArray::from(vec![0]).with_timezone("UTC")),
+                col("naive1", TimestampMicrosecondArray::from(vec![0])),
+                col("naive2", TimestampMicrosecondArray::from(vec![0])),
+            ],
+        );
+        create_server("df_server", "");
+        create_foreign_table(
+            "ft",
+            "zoned1 timestamptz, zoned2 timestamp, naive1 timestamp, naive2 timestamptz",
+            dir,
+            "arrow",
+        );
+        Spi::run("SET LOCAL TimeZone = 'Asia/Tokyo'").unwrap();
+
+        let values = Spi::get_one::<String>("SELECT concat_ws('|', zoned1, zoned2, naive1, naive2) FROM ft");
+        assert_eq!(
+            Ok(Some(String::from(
+                "1970-01-01 09:00:00+09|1970-01-01 09:00:00|1970-01-01 00:00:00|1970-01-01 00:00:00+09"
+            ))),
+            values
+        );
+    }
+
+    #[pg_test]
+    fn test_dates_and_times() {
+        let dir = "/tmp/datafusion_fdw_test/dates";
+        write_arrow(
+            dir,
+            vec![
+                col("d", Date32Array::from(vec![19723])),
+                col("t", Time64MicrosecondArray::from(vec![3_723_000_000])),
+            ],
+        );
+        create_server("df_server", "");
+        create_foreign_table("ft", "d date, t time", dir, "arrow");
+
+        let values = Spi::get_one::<String>("SELECT concat_ws('|', d, t) FROM ft");
+        assert_eq!(Ok(Some(String::from("2024-01-01|01:02:03"))), values);
+    }
+
+    #[pg_test]
+    fn test_intervals_and_durations() {
+        let dir = "/tmp/datafusion_fdw_test/intervals";
+        write_arrow(
+            dir,
+            vec![
+                col(
+                    "i",
+                    IntervalMonthDayNanoArray::from(vec![IntervalMonthDayNanoType::make_value(14, 3, 4_000_000_000)]),
+                ),
+                col("du", DurationMillisecondArray::from(vec![90_000])),
+            ],
+        );
+        create_server("df_server", "");
+        create_foreign_table("ft", "i interval, du interval", dir, "arrow");
+
+        let values = Spi::get_one::<String>("SELECT concat_ws('|', i, du) FROM ft");
+        assert_eq!(Ok(Some(String::from("1 year 2 mons 3 days 00:00:04|00:01:30"))), values);
+    }
+
+    #[pg_test]
+    fn test_binaries_and_uuids() {
+        let dir = "/tmp/datafusion_fdw_test/binaries";
+        write_arrow(
+            dir,
+            vec![
+                col("b", BinaryArray::from(vec![&[1u8, 255u8][..]])),
+                col(
+                    "u",
+                    FixedSizeBinaryArray::try_from_iter(vec![(0u8..16).collect::<Vec<_>>()].into_iter()).unwrap(),
+                ),
+            ],
+        );
+        create_server("df_server", "");
+        create_foreign_table("ft", "b bytea, u uuid", dir, "arrow");
+
+        let values = Spi::get_one::<String>("SELECT concat_ws('|', b, u) FROM ft");
+        assert_eq!(Ok(Some(String::from("\\x01ff|00010203-0405-0607-0809-0a0b0c0d0e0f"))), values);
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "value too long for type character varying(3)")]
+    fn test_varchar_overflow_is_an_error() {
+        let dir = "/tmp/datafusion_fdw_test/varchar";
+        write_arrow(dir, vec![col("s", StringArray::from(vec!["abcdef"]))]);
+        create_server("df_server", "");
+        create_foreign_table("ft", "s varchar(3)", dir, "arrow");
+
+        Spi::run("SELECT s FROM ft").unwrap();
+    }
+
+    #[pg_test]
+    fn test_strings_and_dictionaries_to_enums() {
+        let dir = "/tmp/datafusion_fdw_test/dictionaries";
+        write_arrow(
+            dir,
+            vec![
+                col("m", StringArray::from(vec!["happy"])),
+                col("dict", vec!["happy"].into_iter().collect::<DictionaryArray<Int32Type>>()),
+            ],
+        );
+        create_server("df_server", "");
+        Spi::run("CREATE TYPE mood AS ENUM ('sad', 'happy')").unwrap();
+        create_foreign_table("ft", "m mood, dict text", dir, "arrow");
+
+        let values = Spi::get_one::<String>("SELECT concat_ws('|', m, dict) FROM ft");
+        assert_eq!(Ok(Some(String::from("happy|happy"))), values);
+    }
+
+    #[pg_test]
+    fn test_lists_to_arrays() {
+        let dir = "/tmp/datafusion_fdw_test/lists";
+        write_arrow(
+            dir,
+            vec![col(
+                "l",
+                ListArray::from_iter_primitive::<Int32Type, _, _>(vec![Some(vec![Some(1), None, Some(3)])]),
+            )],
+        );
+        create_server("df_server", "");
+        create_foreign_table("ft", "l integer[]", dir, "arrow");
+
+        let values = Spi::get_one::<String>("SELECT l::text FROM ft");
+        assert_eq!(Ok(Some(String::from("{1,NULL,3}"))), values);
+    }
+
+    #[pg_test]
+    fn test_structs_to_jsonb_and_composites() {
+        let dir = "/tmp/datafusion_fdw_test/structs";
+        let point = || -> ArrayRef {
+            Arc::new(StructArray::from(vec![
+                (
+                    Arc::new(Field::new("a", DataType::Int32, true)),
+                    Arc::new(Int32Array::from(vec![1])) as ArrayRef,
+                ),
+                (
+                    Arc::new(Field::new("b", DataType::Utf8, true)),
+                    Arc::new(StringArray::from(vec!["x"])) as ArrayRef,
+                ),
+            ]))
+        };
+        write_arrow(dir, vec![("j", point()), ("p", point())]);
+        create_server("df_server", "");
+        Spi::run("CREATE TYPE pair AS (a integer, b text)").unwrap();
+        create_foreign_table("ft", "j jsonb, p pair", dir, "arrow");
+
+        let values = Spi::get_one::<String>("SELECT concat_ws('|', j, p) FROM ft");
+        assert_eq!(Ok(Some(String::from(r#"{"a": 1, "b": "x"}|(1,x)"#))), values);
+    }
+
+    #[pg_test]
+    fn test_nulls_propagate() {
+        let dir = "/tmp/datafusion_fdw_test/nulls";
+        write_arrow(
+            dir,
+            vec![
+                col("n", Int64Array::from(vec![Some(1), None])),
+                col("s", StringArray::from(vec![None, Some("x")])),
+                col("ts", TimestampMicrosecondArray::from(vec![None, Some(0)])),
+            ],
+        );
+        create_server("df_server", "");
+        create_foreign_table("ft", "n bigint, s text, ts timestamp", dir, "arrow");
+
+        let values = Spi::get_one::<String>(
+            "SELECT string_agg(concat_ws(':', coalesce(n::text, '-'), coalesce(s, '-'), ts IS NULL), ','
+                ORDER BY n NULLS LAST) FROM ft",
+        );
+        assert_eq!(Ok(Some(String::from("1:-:true,-:x:false"))), values);
+    }
+
+    #[pg_test]
+    fn test_lossless_casts_read_into_declared_types() {
+        let dir = "/tmp/datafusion_fdw_test/casts";
+        let half = cast(&Float32Array::from(vec![1.5]), &DataType::Float16).unwrap();
+        write_arrow(
+            dir,
+            vec![
+                col("u", UInt32Array::from(vec![u32::MAX])),
+                ("h", half),
+                col("i", Int32Array::from(vec![-42])),
+            ],
+        );
+        create_server("df_server", "");
+        create_foreign_table("ft", "u bigint, h real, i numeric", dir, "arrow");
+
+        let values = Spi::get_one::<String>("SELECT concat_ws('|', u, h, i) FROM ft");
+        assert_eq!(Ok(Some(String::from("4294967295|1.5|-42"))), values);
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "cannot be read into Postgres type integer")]
+    fn test_lossy_cast_is_rejected() {
+        let dir = "/tmp/datafusion_fdw_test/lossy";
+        write_arrow(dir, vec![col("n", Int64Array::from(vec![1]))]);
+        create_server("df_server", "");
+        create_foreign_table("ft", "n integer", dir, "arrow");
+
+        Spi::run("SELECT n FROM ft").unwrap();
+    }
+
+    #[pg_test]
+    fn test_import_foreign_schema() {
+        let root = "/tmp/datafusion_fdw_test/import";
+        let _ = std::fs::remove_dir_all(root);
+        write_arrow(
+            &format!("{root}/Orders"),
+            vec![
+                col("OrderID", Int64Array::from(vec![1])),
+                col("note", StringArray::from(vec!["first"])),
+                col("nothing", NullArray::new(1)),
+            ],
+        );
+        write_arrow(&format!("{root}/customers"), vec![col("id", Int64Array::from(vec![1]))]);
+        write_arrow(&format!("{root}/returns"), vec![col("id", Int64Array::from(vec![1]))]);
+        create_server("df_server", "");
+        Spi::run("CREATE SCHEMA limited").unwrap();
+        Spi::run("CREATE SCHEMA excepted").unwrap();
+
+        Spi::run(&format!(
+            "IMPORT FOREIGN SCHEMA \"{root}\" LIMIT TO (orders) FROM SERVER df_server INTO limited
+                OPTIONS (format 'arrow')"
+        ))
+        .unwrap();
+        Spi::run(&format!(
+            "IMPORT FOREIGN SCHEMA \"{root}\" EXCEPT (orders) FROM SERVER df_server INTO excepted
+                OPTIONS (format 'arrow')"
+        ))
+        .unwrap();
+
+        let tables = |schema: &str| {
+            Spi::get_one::<String>(&format!(
+                "SELECT string_agg(relname, ',' ORDER BY relname) FROM pg_class
+                    WHERE relnamespace = '{schema}'::regnamespace"
+            ))
+        };
+        assert_eq!(Ok(Some(String::from("orders"))), tables("limited"));
+        assert_eq!(Ok(Some(String::from("customers,returns"))), tables("excepted"));
+
+        // the null column has no Postgres type and is skipped
+        let columns = Spi::get_one::<String>(
+            "SELECT string_agg(attname || coalesce(':' || array_to_string(attfdwoptions, ','), ''), ',' ORDER BY attnum)
+                FROM pg_attribute WHERE attrelid = 'limited.orders'::regclass AND attnum > 0",
+        );
+        assert_eq!(Ok(Some(String::from("orderid:column_name=OrderID,note"))), columns);
+
+        let values = Spi::get_one::<String>("SELECT concat_ws('|', orderid, note) FROM limited.orders");
+        assert_eq!(Ok(Some(String::from("1|first"))), values);
+    }
+
+    #[pg_test]
+    fn test_datafusion_settings_apply_to_the_session() {
+        let dir = "/tmp/datafusion_fdw_test/settings";
+        write_arrow(dir, vec![col("n", Int64Array::from(vec![1, 2, 3]))]);
+        create_server("df_server", "OPTIONS (\"datafusion.execution.batch_size\" '1')");
+        Spi::run(&format!(
+            "CREATE FOREIGN TABLE ft (n bigint) SERVER df_server
+                OPTIONS (location '{dir}/', format 'arrow', \"datafusion.execution.target_partitions\" '1')"
+        ))
+        .unwrap();
+
+        // one row per batch
+        let values = Spi::get_one::<String>("SELECT string_agg(n::text, ',' ORDER BY n) FROM ft");
+        assert_eq!(Ok(Some(String::from("1,2,3"))), values);
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "invalid DataFusion setting \"datafusion.execution.batch_size\"")]
+    fn test_validator_rejects_bad_datafusion_setting() {
+        create_server("df_server", "OPTIONS (\"datafusion.execution.batch_size\" 'many')");
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "cannot open foreign table \"ft\"")]
+    fn test_bad_datafusion_setting_without_validator_is_an_error() {
+        let dir = "/tmp/datafusion_fdw_test/settings_unchecked";
+        write_arrow(dir, vec![col("n", Int64Array::from(vec![1]))]);
+        Spi::run("CREATE FOREIGN DATA WRAPPER datafusion_wrapper HANDLER datafusion_fdw_handler").unwrap();
+        Spi::run(
+            "CREATE SERVER df_server FOREIGN DATA WRAPPER datafusion_wrapper
+                OPTIONS (\"datafusion.execution.batch_size\" 'many')",
+        )
+        .unwrap();
+        create_foreign_table("ft", "n bigint", dir, "arrow");
+
+        Spi::run("SELECT n FROM ft").unwrap();
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "invalid option \"bucekt\" for server")]
+    fn test_validator_rejects_unknown_option() {
+        Spi::run(
+            "CREATE FOREIGN DATA WRAPPER datafusion_wrapper
+                HANDLER datafusion_fdw_handler VALIDATOR datafusion_fdw_validator",
+        )
+        .unwrap();
+        Spi::run("CREATE SERVER df_server FOREIGN DATA WRAPPER datafusion_wrapper OPTIONS (bucekt 'data1')")
+            .unwrap();
+    }
+
+    #[pg_test]
+    #[should_panic(expected = "only superuser or a role with privileges of the pg_read_server_files role")]
+    fn test_local_location_requires_read_server_files() {
+        create_server("df_server", "");
+        Spi::run("CREATE ROLE df_user").unwrap();
+        Spi::run("GRANT USAGE ON FOREIGN SERVER df_server TO df_user").unwrap();
+        Spi::run("DO $$ BEGIN EXECUTE format('GRANT CREATE ON SCHEMA %I TO df_user', current_schema()); END $$")
+            .unwrap();
+        Spi::run("SET LOCAL ROLE df_user").unwrap();
+
+        // object store locations are fine
+        Spi::run("CREATE FOREIGN TABLE remote (id bigint) SERVER df_server OPTIONS (location 's3://data1/remote/')")
+            .unwrap();
+        Spi::run("CREATE FOREIGN TABLE ft (line text) SERVER df_server OPTIONS (location '/etc/passwd', format 'csv')")
+            .unwrap();
+    }
+
+    // ft reads a fixture through the wrapper, lt is a local copy of it
+    fn create_pushdown_tables(dir: &str) {
+        let days = |d: i32| 19_723 + d; // 2024-01-01
+        let micros = |d: i64, h: i64| (19_723 + d) * 86_400_000_000 + h * 3_600_000_000;
+        write_arrow(
+            dir,
+            vec![
+                col("id", Int64Array::from(vec![1, 2, 3, 4, 5, 6, 7, 8])),
+                col("i", Int32Array::from(vec![Some(1), Some(0), Some(-3), None, Some(5), Some(100), Some(7), Some(-8)])),
+                col("n", Int64Array::from(vec![Some(10), Some(20), None, Some(40), Some(-5), Some(60), Some(70), Some(80)])),
+                col(
+                    "s",
+                    StringArray::from(vec![
+                        Some("apple"),
+                        Some("Banana"),
+                        Some("cherry pie"),
+                        None,
+                        Some("apple2"),
+                        Some("Äpfel"),
+                        Some("a_b%c"),
+                        Some("line1\nline2"),
+                    ]),
+                ),
+                col(
+                    "d",
+                    Date32Array::from(vec![
+                        Some(days(0)),
+                        Some(days(45)),
+                        None,
+                        Some(days(120)),
+                        Some(days(-1)),
+                        Some(days(152)),
+                        Some(days(188)),
+                        Some(days(212)),
+                    ]),
+                ),
+                col(
+                    "ts",
+                    TimestampMicrosecondArray::from(vec![
+                        Some(micros(0, 10)),
+                        Some(micros(45, 0)),
+                        Some(micros(60, 23)),
+                        None,
+                        Some(micros(-1, 12)),
+                        Some(micros(152, 6)),
+                        Some(micros(188, 7)),
+                        Some(micros(212, 18)),
+                    ]),
+                ),
+                col(
+                    "tz",
+                    TimestampMicrosecondArray::from(vec![
+                        Some(micros(0, 10)),
+                        None,
+                        Some(micros(60, 23)),
+                        Some(micros(120, 1)),
+                        Some(micros(-1, 12)),
+                        Some(micros(152, 6)),
+                        Some(micros(188, 7)),
+                        Some(micros(212, 18)),
+                    ])
+                    .with_timezone("UTC"),
+                ),
+                col("f", Float64Array::from(vec![Some(1.5), Some(-2.25), Some(0.0), Some(3.0), None, Some(1e10), Some(0.5), Some(-0.0)])),
+            ],
+        );
+        create_server("df_server", "");
+        create_foreign_table(
+            "ft",
+            "id bigint, i integer, n bigint, s text, d date, ts timestamp, tz timestamptz, f float8",
+            dir,
+            "arrow",
+        );
+        Spi::run("CREATE TEMP TABLE lt AS SELECT * FROM ft").unwrap();
+    }
+
+    fn explain(query: &str) -> String {
+        Spi::connect(|client| {
+            client
+                .select(&format!("EXPLAIN (VERBOSE) {query}"), None, None)
+                .unwrap()
+                .filter_map(|row| row.get::<String>(1).unwrap())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+    }
+
+    // the rows of ft matching cond, with the condition evaluated by DataFusion
+    // where it is pushed down, are the rows Postgres finds in lt
+    fn assert_same_rows(cond: &str) {
+        let ids = |table: &str| {
+            Spi::get_one::<String>(&format!(
+                "SELECT coalesce(string_agg(id::text, ',' ORDER BY id), '') FROM {table} WHERE {cond}"
+            ))
+            .unwrap()
+        };
+        assert_eq!(ids("lt"), ids("ft"), "{cond}");
+    }
+
+    fn assert_pushed(cond: &str) {
+        let plan = explain(&format!("SELECT id FROM ft WHERE {cond}"));
+        assert!(plan.contains(" where ") && !plan.contains("Filter:"), "{cond}: {plan}");
+    }
+
+    fn assert_not_pushed(cond: &str) {
+        let plan = explain(&format!("SELECT id FROM ft WHERE {cond}"));
+        assert!(!plan.contains(" where ") && plan.contains("Filter:"), "{cond}: {plan}");
+    }
+
+    #[pg_test]
+    fn test_pushed_comparisons_match_local_results() {
+        create_pushdown_tables("/tmp/datafusion_fdw_test/pushdown_comparisons");
+
+        for cond in [
+            "n = 20",
+            "n <> 20",
+            "n < 40",
+            "40 <= n",
+            "n > id",
+            "s = 'apple'",
+            "s <> 'apple'",
+            "s COLLATE \"C\" < 'b'",
+            "d > '2024-03-01'",
+            "d <= '2024-01-01'",
+            "ts < '2024-03-01 23:00'",
+            "ts >= '2024-06-01 06:00'",
+            "tz < '2024-03-01 00:00+00'",
+        ] {
+            assert_pushed(cond);
+            assert_same_rows(cond);
+        }
+
+        // comparing timestamp with timestamptz depends on the session time
+        // zone, DataFusion orders -0.0 before 0.0
+        for cond in [
+            "ts < '2024-03-01 23:00'::timestamptz",
+            "f = 0",
+            "f < 0",
+            "f >= 1.5",
+            "f IN (0, 1.5)",
+        ] {
+            assert_not_pushed(cond);
+            assert_same_rows(cond);
+        }
+    }
+
+    #[pg_test]
+    fn test_coarse_timestamp_units_stay_local() {
+        let dir = "/tmp/datafusion_fdw_test/pushdown_units";
+        write_arrow(
+            dir,
+            vec![
+                col("id", Int64Array::from(vec![1, 2])),
+                col("ts", TimestampMillisecondArray::from(vec![0, 1])),
+            ],
+        );
+        create_server("df_server", "");
+        create_foreign_table("ft", "id bigint, ts timestamp", dir, "arrow");
+        Spi::run("CREATE TEMP TABLE lt AS SELECT * FROM ft").unwrap();
+
+        // the literal isn't a whole number of milliseconds
+        assert_not_pushed("ts >= '1970-01-01 00:00:00.0005'");
+        assert_same_rows("ts >= '1970-01-01 00:00:00.0005'");
+    }
+
+    #[pg_test]
+    fn test_pushed_lists_and_null_tests_match_local_results() {
+        create_pushdown_tables("/tmp/datafusion_fdw_test/pushdown_lists");
+
+        for cond in [
+            "n IN (10, 40, 70)",
+            "n NOT IN (10, 40)",
+            "n = ANY ('{20,60}')",
+            "n <> ALL ('{20,60}')",
+            "s IN ('apple', 'Banana')",
+            "s NOT IN ('apple')",
+            "n IS NULL",
+            "n IS NOT NULL",
+            "s IS NULL OR n > 50",
+            "d IS NOT NULL AND tz IS NULL",
+        ] {
+            assert_pushed(cond);
+            assert_same_rows(cond);
+        }
+
+        // a null in the list leaves IN true on a match, NOT IN never true
+        for cond in ["n IN (10, NULL)", "n NOT IN (10, NULL)"] {
+            assert_same_rows(cond);
+        }
+    }
+
+    #[pg_test]
+    fn test_partially_pushed_conditions_match_local_results() {
+        create_pushdown_tables("/tmp/datafusion_fdw_test/pushdown_partial");
+
+        // the shippable conjunct is pushed down, the other one stays local
+        for cond in ["n > 10 AND md5(s) <> md5('Banana')", "s IS NOT NULL AND md5(s) LIKE '%5%'"] {
+            let plan = explain(&format!("SELECT id FROM ft WHERE {cond}"));
+            assert!(plan.contains(" where ") && plan.contains("Filter:"), "{cond}: {plan}");
+            assert_same_rows(cond);
+        }
+
+        // a disjunction is pushed down as a whole or not at all
+        for cond in ["n = 10 OR md5(s) = md5('Banana')", "NOT (n > 20 OR md5(s) = md5('apple'))"] {
+            assert_not_pushed(cond);
+            assert_same_rows(cond);
+        }
+        for cond in ["NOT (n > 20 AND s IS NOT NULL)", "(n < 20 OR d > '2024-06-01') AND NOT s = 'apple'"] {
+            assert_pushed(cond);
+            assert_same_rows(cond);
+        }
+    }
+
+    // a deterministic collation other than C, if the database has one
+    fn other_collation() -> Option<String> {
+        Spi::get_one::<String>(
+            "SELECT coalesce(
+                (SELECT '\"default\"' FROM pg_database
+                    WHERE datname = current_database() AND datcollate NOT IN ('C', 'POSIX')),
+                (SELECT quote_ident(collname) FROM pg_collation
+                    WHERE collname NOT IN ('default', 'ucs_basic')
+                        AND coalesce(collcollate, '') NOT IN ('C', 'POSIX')
+                        AND collisdeterministic
+                        AND collencoding IN (-1, pg_char_to_encoding(getdatabaseencoding()))
+                    ORDER BY collname LIMIT 1))",
+        )
+        .unwrap()
+    }
+
+    #[pg_test]
+    fn test_pushed_pattern_matches_follow_collation() {
+        create_pushdown_tables("/tmp/datafusion_fdw_test/pushdown_patterns");
+
+        for collation in std::iter::once(String::from("\"C\"")).chain(other_collation()) {
+            for (op, pattern) in [
+                ("LIKE", "a%"),
+                ("NOT LIKE", "%e%"),
+                ("LIKE", "a\\_b%"),
+                ("ILIKE", "A%"),
+                ("NOT ILIKE", "%PIE"),
+                ("~", "^[a-c]"),
+                ("!~", "e$"),
+                ("~", "line1.line2"),
+                ("~*", "^b"),
+                ("!~*", "APPLE"),
+                ("~", "^[[:upper:]]"),
+                ("~", "\\mpie"),
+                ("~", "[a&&b]"),
+                ("~", "[[a]"),
+                ("~", "^[^a-c]+$"),
+            ] {
+                assert_same_rows(&format!("s COLLATE {collation} {op} '{pattern}'"));
+            }
+        }
+
+        assert_pushed("s COLLATE \"C\" LIKE 'a%'");
+        assert_pushed("s COLLATE \"C\" ~ '^[[:upper:]]'");
+        // LIKE's default escape is a backslash, Rust's \m isn't a word start
+        assert_not_pushed("s LIKE 'a\\_b%'");
+        assert_not_pushed("s ~ '\\mpie'");
+        // set operations and nested classes in Rust, literals in Postgres
+        assert_not_pushed("s COLLATE \"C\" ~ '[a&&b]'");
+        assert_not_pushed("s COLLATE \"C\" ~ '[[a]'");
+        assert_pushed("s COLLATE \"C\" ~ '^[^a-c]+$'");
+    }
+
+    #[pg_test]
+    fn test_pushed_functions_casts_and_case_match_local_results() {
+        create_pushdown_tables("/tmp/datafusion_fdw_test/pushdown_functions");
+
+        for cond in [
+            "date_trunc('day', ts) = '2024-02-15'",
+            "date_trunc('month', ts) >= '2024-03-01'",
+            "extract(year from d) = 2024",
+            "i + 1 > 3",
+            "i * 2 < 5",
+            "i / 2 = 0",
+            "i % 3 = 1",
+            "n::text = '20'",
+            "CASE WHEN n > 50 THEN 'big' WHEN n > 0 THEN 'small' END = 'small'",
+            "CASE s WHEN 'apple' THEN 1 ELSE 0 END = 0",
+        ] {
+            assert_pushed(cond);
+            assert_same_rows(cond);
+        }
+
+        // case folding is only shipped under a collation other than C
+        for collation in std::iter::once(String::from("\"C\"")).chain(other_collation()) {
+            assert_same_rows(&format!("lower(s COLLATE {collation}) = 'banana'"));
+            assert_same_rows(&format!("upper(s COLLATE {collation}) LIKE 'APPLE%'"));
+        }
+        if let Some(collation) = other_collation() {
+            assert_pushed(&format!("lower(s COLLATE {collation}) = 'banana'"));
+        }
+
+        // the division stays local, DataFusion would divide by zero in the
+        // row the other condition filters out
+        let cond = "i <> 0 AND 100 / i > 5";
+        let plan = explain(&format!("SELECT id FROM ft WHERE {cond}"));
+        assert!(plan.contains(" where ") && plan.contains("Filter:"), "{plan}");
+        assert_same_rows(cond);
+
+        // int8 arithmetic can overflow, casts from char(n) trim blanks
+        for cond in ["n + 1 > 20", "-n < 0", "s::char(6)::text = 'apple'"] {
+            assert_not_pushed(cond);
+            assert_same_rows(cond);
+        }
+    }
+
+    #[pg_test]
+    fn test_parameterized_nested_loop_matches_local_results() {
+        create_pushdown_tables("/tmp/datafusion_fdw_test/pushdown_nested_loop");
+        Spi::run("CREATE TEMP TABLE keys (k bigint)").unwrap();
+        Spi::run("INSERT INTO keys VALUES (10), (40), (40), (99), (NULL), (80)").unwrap();
+        Spi::run("ANALYZE keys").unwrap();
+        Spi::run("SET LOCAL enable_hashjoin = off").unwrap();
+        Spi::run("SET LOCAL enable_mergejoin = off").unwrap();
+        Spi::run("SET LOCAL enable_material = off").unwrap();
+
+        let query = |table: &str| {
+            format!(
+                "SELECT string_agg(k || ':' || t.id, ',' ORDER BY k, t.id)
+                    FROM keys JOIN {table} t ON t.n = keys.k"
+            )
+        };
+
+        // the inner scan is rerun with the key of each outer row
+        let plan = explain(&query("ft"));
+        assert!(plan.contains("Nested Loop") && plan.contains("where n = $1"), "{plan}");
+
+        let local = Spi::get_one::<String>(&query("lt")).unwrap();
+        let remote = Spi::get_one::<String>(&query("ft")).unwrap();
+        assert_eq!(Some(String::from("10:1,40:4,40:4,80:8")), local);
+        assert_eq!(local, remote);
+    }
+
+    #[pg_test]
+    fn test_parameterized_scan_on_text_key_matches_local_results() {
+        create_pushdown_tables("/tmp/datafusion_fdw_test/pushdown_text_key");
+        Spi::run("CREATE TEMP TABLE keys (name text)").unwrap();
+        Spi::run("INSERT INTO keys VALUES ('apple'), ('Äpfel'), ('Äpfel'), ('pear'), (NULL), ('a_b%c')").unwrap();
+        Spi::run("ANALYZE keys").unwrap();
+        Spi::run("SET LOCAL enable_hashjoin = off").unwrap();
+        Spi::run("SET LOCAL enable_mergejoin = off").unwrap();
+        Spi::run("SET LOCAL enable_material = off").unwrap();
+
+        // the key is only set once the inner scan has started
+        let join = |table: &str| {
+            format!(
+                "SELECT string_agg(name || ':' || t.id, ',' ORDER BY t.id)
+                    FROM keys JOIN {table} t ON t.s = keys.name"
+            )
+        };
+        let plan = explain(&join("ft"));
+        assert!(plan.contains("Nested Loop") && plan.contains("where s = $1"), "{plan}");
+
+        let local = Spi::get_one::<String>(&join("lt")).unwrap();
+        let remote = Spi::get_one::<String>(&join("ft")).unwrap();
+        assert_eq!(Some(String::from("apple:1,Äpfel:6,Äpfel:6,a_b%c:7")), local);
+        assert_eq!(local, remote);
+
+        // a correlated subquery rescans ft for each row of keys
+        let subquery = |table: &str| {
+            format!(
+                "SELECT string_agg((SELECT count(*) FROM {table} WHERE {table}.s = t.name)::text, ',' ORDER BY t.name)
+                    FROM keys t"
+            )
+        };
+        let local = Spi::get_one::<String>(&subquery("lt")).unwrap();
+        let remote = Spi::get_one::<String>(&subquery("ft")).unwrap();
+        assert_eq!(local, remote);
+    }
+
+    #[pg_test]
+    fn test_infinite_parameters_match_local_results() {
+        create_pushdown_tables("/tmp/datafusion_fdw_test/pushdown_infinity");
+        Spi::run("SET LOCAL plan_cache_mode = force_generic_plan").unwrap();
+
+        let ids = |query: &str| Spi::get_one::<String>(query).unwrap().unwrap_or_default();
+        for (column, type_name) in [("ts", "timestamp"), ("tz", "timestamptz"), ("d", "date")] {
+            for (op, value) in [("<", "infinity"), (">", "-infinity"), ("=", "infinity")] {
+                let query = |table: &str| {
+                    format!(
+                        "SELECT coalesce(string_agg(id::text, ',' ORDER BY id), '') FROM {table} WHERE {column} {op} $1"
+                    )
+                };
+                Spi::run(&format!("PREPARE q_ft({type_name}) AS {}", query("ft"))).unwrap();
+                Spi::run(&format!("PREPARE q_lt({type_name}) AS {}", query("lt"))).unwrap();
+
+                let plan = explain(&format!("EXECUTE q_ft('{value}')"));
+                assert!(plan.contains(&format!("where {column} {op} $1")), "{plan}");
+                assert_eq!(
+                    ids(&format!("EXECUTE q_lt('{value}')")),
+                    ids(&format!("EXECUTE q_ft('{value}')")),
+                    "{column} {op} {value}"
+                );
+
+                Spi::run("DEALLOCATE q_ft").unwrap();
+                Spi::run("DEALLOCATE q_lt").unwrap();
+            }
+        }
+    }
+
+    #[pg_test]
+    fn test_rescan_without_parameters_returns_the_same_rows() {
+        create_pushdown_tables("/tmp/datafusion_fdw_test/pushdown_rescan");
+        Spi::run("CREATE TEMP TABLE keys (k bigint)").unwrap();
+        Spi::run("INSERT INTO keys VALUES (1), (2), (3)").unwrap();
+        Spi::run("ANALYZE keys").unwrap();
+        Spi::run("SET LOCAL enable_hashjoin = off").unwrap();
+        Spi::run("SET LOCAL enable_mergejoin = off").unwrap();
+        Spi::run("SET LOCAL enable_material = off").unwrap();
+
+        // the inner scan is rescanned for each key with nothing changed
+        let query = |table: &str| {
+            format!(
+                "SELECT string_agg(k || ':' || t.id, ',' ORDER BY k, t.id)
+                    FROM keys JOIN {table} t ON t.id > keys.k * 2 AND t.n < 70"
+            )
+        };
+        let plan = explain(&query("ft"));
+        assert!(plan.contains("Nested Loop") && !plan.contains("$1"), "{plan}");
+
+        let local = Spi::get_one::<String>(&query("lt")).unwrap();
+        let remote = Spi::get_one::<String>(&query("ft")).unwrap();
+        assert_eq!(Some(String::from("1:4,1:5,1:6,2:5,2:6")), local);
+        assert_eq!(local, remote);
+    }
 }
 
 /// This module is required by `cargo pgrx test` invocations.
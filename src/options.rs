@@ -0,0 +1,595 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use datafusion::config::ConfigOptions;
+use datafusion::datasource::file_format::arrow::ArrowFormat;
+use datafusion::datasource::file_format::csv::CsvFormat;
+use datafusion::datasource::file_format::json::JsonFormat;
+use datafusion::datasource::file_format::parquet::ParquetFormat;
+use datafusion::datasource::file_format::FileFormat;
+
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::prelude::*;
+use pgrx::PgList;
+
+// catalog oids passed to the validator, see catalog/pg_*.h
+pub const FOREIGN_DATA_WRAPPER_RELATION_ID: pg_sys::Oid =
+    unsafe { pg_sys::Oid::from_u32_unchecked(2328) };
+pub const FOREIGN_SERVER_RELATION_ID: pg_sys::Oid =
+    unsafe { pg_sys::Oid::from_u32_unchecked(1417) };
+pub const USER_MAPPING_RELATION_ID: pg_sys::Oid =
+    unsafe { pg_sys::Oid::from_u32_unchecked(1418) };
+pub const FOREIGN_TABLE_RELATION_ID: pg_sys::Oid =
+    unsafe { pg_sys::Oid::from_u32_unchecked(3118) };
+pub const ATTRIBUTE_RELATION_ID: pg_sys::Oid = unsafe { pg_sys::Oid::from_u32_unchecked(1249) };
+// ROLE_PG_READ_SERVER_FILES in catalog/pg_authid.dat
+const ROLE_PG_READ_SERVER_FILES: pg_sys::Oid = unsafe { pg_sys::Oid::from_u32_unchecked(4569) };
+
+// an option accepted by datafusion_fdw, and the catalog it may be attached to
+struct DataFusionFdwOption {
+    name: &'static str,
+    catalog: pg_sys::Oid,
+    check: fn(&str) -> Result<(), String>,
+}
+
+static DATAFUSION_FDW_OPTIONS: &[DataFusionFdwOption] = &[
+    // server
+    DataFusionFdwOption {
+        name: "endpoint",
+        catalog: FOREIGN_SERVER_RELATION_ID,
+        check: check_not_empty,
+    },
+    DataFusionFdwOption {
+        name: "region",
+        catalog: FOREIGN_SERVER_RELATION_ID,
+        check: check_not_empty,
+    },
+    DataFusionFdwOption {
+        name: "bucket",
+        catalog: FOREIGN_SERVER_RELATION_ID,
+        check: check_not_empty,
+    },
+    DataFusionFdwOption {
+        name: "catalog_root",
+        catalog: FOREIGN_SERVER_RELATION_ID,
+        check: check_not_empty,
+    },
+    DataFusionFdwOption {
+        name: "allow_http",
+        catalog: FOREIGN_SERVER_RELATION_ID,
+        check: check_bool,
+    },
+    DataFusionFdwOption {
+        name: "virtual_hosted_style",
+        catalog: FOREIGN_SERVER_RELATION_ID,
+        check: check_bool,
+    },
+    DataFusionFdwOption {
+        name: "use_environment_credentials",
+        catalog: FOREIGN_SERVER_RELATION_ID,
+        check: check_bool,
+    },
+    // user mapping
+    DataFusionFdwOption {
+        name: "access_key_id",
+        catalog: USER_MAPPING_RELATION_ID,
+        check: check_not_empty,
+    },
+    DataFusionFdwOption {
+        name: "secret_access_key",
+        catalog: USER_MAPPING_RELATION_ID,
+        check: check_not_empty,
+    },
+    DataFusionFdwOption {
+        name: "session_token",
+        catalog: USER_MAPPING_RELATION_ID,
+        check: check_not_empty,
+    },
+    // foreign table
+    DataFusionFdwOption {
+        name: "location",
+        catalog: FOREIGN_TABLE_RELATION_ID,
+        check: check_not_empty,
+    },
+    DataFusionFdwOption {
+        name: "path",
+        catalog: FOREIGN_TABLE_RELATION_ID,
+        check: check_not_empty,
+    },
+    DataFusionFdwOption {
+        name: "format",
+        catalog: FOREIGN_TABLE_RELATION_ID,
+        check: check_format,
+    },
+    DataFusionFdwOption {
+        name: "table_name",
+        catalog: FOREIGN_TABLE_RELATION_ID,
+        check: check_catalog_name,
+    },
+    DataFusionFdwOption {
+        name: "schema_name",
+        catalog: FOREIGN_TABLE_RELATION_ID,
+        check: check_catalog_name,
+    },
+    // column
+    DataFusionFdwOption {
+        name: "column_name",
+        catalog: ATTRIBUTE_RELATION_ID,
+        check: check_not_empty,
+    },
+];
+
+// DataFusion configuration keys, e.g. datafusion.execution.parquet.pushdown_filters,
+// are accepted on servers and foreign tables
+const DATAFUSION_CONFIG_PREFIX: &str = "datafusion.";
+
+fn accepts_datafusion_config(catalog: pg_sys::Oid) -> bool {
+    catalog == FOREIGN_SERVER_RELATION_ID || catalog == FOREIGN_TABLE_RELATION_ID
+}
+
+fn check_datafusion_config(key: &str, value: &str) -> Result<(), String> {
+    ConfigOptions::new().set(key, value).map_err(|e| e.to_string())
+}
+
+fn check_not_empty(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        Err(String::from("The value must not be empty."))
+    } else {
+        Ok(())
+    }
+}
+
+// table and schema names are directories under the server's catalog_root
+fn check_catalog_name(value: &str) -> Result<(), String> {
+    check_not_empty(value)?;
+    if value.contains('/') || value.contains("..") {
+        return Err(String::from("The value must not contain \"/\" or \"..\"."));
+    }
+    Ok(())
+}
+
+fn check_bool(value: &str) -> Result<(), String> {
+    parse_bool(value)
+        .map(|_| ())
+        .ok_or_else(|| String::from("Valid values are: true, false, on, off, yes, no, 1, 0"))
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "on" | "yes" | "1" => Some(true),
+        "false" | "off" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn check_format(value: &str) -> Result<(), String> {
+    DataFormat::from_str(value).map(|_| ())
+}
+
+// a location read from the database server's file system rather than an
+// object store
+fn is_local_location(value: &str) -> bool {
+    match value.split_once("://") {
+        Some((scheme, _)) => scheme.eq_ignore_ascii_case("file"),
+        None => true,
+    }
+}
+
+// local files are read as the server's OS user, the same rule as file_fdw's
+// filename option
+unsafe fn check_local_location(key: &str, value: &str, catalog: pg_sys::Oid) {
+    if is_local_location(value) && !pg_sys::has_privs_of_role(pg_sys::GetUserId(), ROLE_PG_READ_SERVER_FILES) {
+        ErrorReport::new(
+            PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            format!(
+                "only superuser or a role with privileges of the pg_read_server_files role may set a local \"{key}\" for a {}",
+                catalog_name(catalog)
+            ),
+            pg_sys::function_name!(),
+        )
+        .report(PgLogLevel::ERROR)
+    }
+}
+
+fn catalog_name(catalog: pg_sys::Oid) -> &'static str {
+    match catalog {
+        FOREIGN_DATA_WRAPPER_RELATION_ID => "foreign data wrapper",
+        FOREIGN_SERVER_RELATION_ID => "server",
+        USER_MAPPING_RELATION_ID => "user mapping",
+        FOREIGN_TABLE_RELATION_ID => "foreign table",
+        ATTRIBUTE_RELATION_ID => "column",
+        _ => "unknown object",
+    }
+}
+
+fn find_option(name: &str, catalog: pg_sys::Oid) -> Option<&'static DataFusionFdwOption> {
+    DATAFUSION_FDW_OPTIONS
+        .iter()
+        .find(|opt| opt.catalog == catalog && opt.name == name)
+}
+
+fn valid_options_hint(catalog: pg_sys::Oid) -> String {
+    let mut names = DATAFUSION_FDW_OPTIONS
+        .iter()
+        .filter(|opt| opt.catalog == catalog)
+        .map(|opt| opt.name)
+        .collect::<Vec<_>>();
+    if accepts_datafusion_config(catalog) {
+        names.push("datafusion.*");
+    }
+
+    if names.is_empty() {
+        String::from("There are no valid options in this context.")
+    } else {
+        format!("Valid options in this context are: {}", names.join(", "))
+    }
+}
+
+// check the options of a CREATE/ALTER statement before they are stored in the catalog
+#[pg_extern]
+fn datafusion_fdw_validator(options: Vec<Option<String>>, catalog: Option<pg_sys::Oid>) {
+    let Some(catalog) = catalog else {
+        return;
+    };
+
+    let mut keys = vec![];
+
+    for opt in options.into_iter().flatten() {
+        let (key, value) = opt.split_once('=').unwrap_or((opt.as_str(), ""));
+        keys.push(key.to_owned());
+
+        if key.starts_with(DATAFUSION_CONFIG_PREFIX) && accepts_datafusion_config(catalog) {
+            if let Err(reason) = check_datafusion_config(key, value) {
+                ErrorReport::new(
+                    PgSqlErrorCode::ERRCODE_FDW_INVALID_ATTRIBUTE_VALUE,
+                    format!("invalid DataFusion setting \"{key}\": \"{value}\""),
+                    pg_sys::function_name!(),
+                )
+                .set_hint(reason)
+                .report(PgLogLevel::ERROR)
+            }
+            continue;
+        }
+
+        match find_option(key, catalog) {
+            None => ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_FDW_INVALID_OPTION_NAME,
+                format!("invalid option \"{key}\" for {}", catalog_name(catalog)),
+                pg_sys::function_name!(),
+            )
+            .set_hint(valid_options_hint(catalog))
+            .report(PgLogLevel::ERROR),
+            Some(def) => {
+                if let Err(reason) = (def.check)(value) {
+                    ErrorReport::new(
+                        PgSqlErrorCode::ERRCODE_FDW_INVALID_ATTRIBUTE_VALUE,
+                        format!("invalid value for option \"{key}\": \"{value}\""),
+                        pg_sys::function_name!(),
+                    )
+                    .set_hint(reason)
+                    .report(PgLogLevel::ERROR)
+                }
+                if matches!(key, "location" | "path" | "catalog_root") {
+                    unsafe { check_local_location(key, value, catalog) };
+                }
+                // the backend's credentials aren't any role's to hand out
+                if key == "use_environment_credentials" && unsafe { !pg_sys::superuser() } {
+                    ErrorReport::new(
+                        PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+                        "only superuser may set the option \"use_environment_credentials\"",
+                        pg_sys::function_name!(),
+                    )
+                    .report(PgLogLevel::ERROR)
+                }
+            }
+        }
+    }
+
+    if catalog == USER_MAPPING_RELATION_ID {
+        let has_key = keys.iter().any(|k| k == "access_key_id");
+        let has_secret = keys.iter().any(|k| k == "secret_access_key");
+        if has_key != has_secret {
+            ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_FDW_OPTION_NAME_NOT_FOUND,
+                "user mapping requires both \"access_key_id\" and \"secret_access_key\"",
+                pg_sys::function_name!(),
+            )
+            .report(PgLogLevel::ERROR)
+        }
+    }
+
+    // without a location the table resolves against the server's catalog_root,
+    // which the validator can't see
+    if catalog == FOREIGN_TABLE_RELATION_ID {
+        let locations = keys.iter().filter(|k| *k == "location" || *k == "path").count();
+        if locations > 1 {
+            ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_FDW_INVALID_OPTION_NAME,
+                "foreign table accepts only one of the options \"location\" or \"path\"",
+                pg_sys::function_name!(),
+            )
+            .report(PgLogLevel::ERROR)
+        }
+    }
+}
+
+// collect a DefElem list, e.g. ForeignTable.options, into a key/value map
+pub unsafe fn options_to_hashmap(options: *mut pg_sys::List) -> HashMap<String, String> {
+    let mut ret = HashMap::new();
+
+    let options: PgList<pg_sys::DefElem> = PgList::from_pg(options);
+    for def in options.iter_ptr() {
+        let name = CStr::from_ptr((*def).defname).to_str().unwrap().to_owned();
+        let value = CStr::from_ptr(pg_sys::defGetString(def))
+            .to_str()
+            .unwrap()
+            .to_owned();
+        ret.insert(name, value);
+    }
+
+    ret
+}
+
+// name of the Arrow field backing a column, taken from the column_name option
+// and falling back to the Postgres attribute name
+pub unsafe fn remote_column_name(relid: pg_sys::Oid, attnum: i16) -> String {
+    let mut opts = options_to_hashmap(pg_sys::GetForeignColumnOptions(relid, attnum));
+
+    match opts.remove("column_name") {
+        Some(v) => v,
+        None => CStr::from_ptr(pg_sys::get_attname(relid, attnum, false))
+            .to_str()
+            .unwrap()
+            .to_owned(),
+    }
+}
+
+// file formats a foreign table can be read from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DataFormat {
+    Parquet,
+    Csv,
+    Json,
+    Arrow,
+}
+
+impl FromStr for DataFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "parquet" => Ok(DataFormat::Parquet),
+            "csv" => Ok(DataFormat::Csv),
+            "json" => Ok(DataFormat::Json),
+            "arrow" => Ok(DataFormat::Arrow),
+            _ => Err(String::from("Valid formats are: parquet, csv, json, arrow")),
+        }
+    }
+}
+
+impl DataFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataFormat::Parquet => "parquet",
+            DataFormat::Csv => "csv",
+            DataFormat::Json => "json",
+            DataFormat::Arrow => "arrow",
+        }
+    }
+
+    pub fn file_format(&self) -> Arc<dyn FileFormat> {
+        match self {
+            DataFormat::Parquet => Arc::new(ParquetFormat::new()),
+            DataFormat::Csv => Arc::new(CsvFormat::default()),
+            DataFormat::Json => Arc::new(JsonFormat::default()),
+            DataFormat::Arrow => Arc::new(ArrowFormat),
+        }
+    }
+
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            DataFormat::Parquet => ".parquet",
+            DataFormat::Csv => ".csv",
+            DataFormat::Json => ".json",
+            DataFormat::Arrow => ".arrow",
+        }
+    }
+}
+
+// options of a foreign server, read from pg_foreign_server
+#[derive(Debug, Clone, Default)]
+pub struct ServerOptions {
+    pub endpoint: Option<String>,
+    pub region: Option<String>,
+    // directory holding one dataset per <schema_name>/<table_name>, for
+    // foreign tables without a location
+    pub catalog_root: Option<String>,
+    // no object store is registered when the server has no bucket
+    pub bucket: Option<String>,
+    pub allow_http: bool,
+    pub virtual_hosted_style: bool,
+    // read with the backend's AWS_* variables or instance role when the user
+    // mapping has no credentials
+    pub use_environment_credentials: bool,
+    // datafusion.* configuration keys
+    pub df_settings: Vec<(String, String)>,
+}
+
+impl ServerOptions {
+    pub unsafe fn from_foreign_server(serverid: pg_sys::Oid) -> Self {
+        let server = pg_sys::GetForeignServer(serverid);
+        let mut opts = options_to_hashmap((*server).options);
+
+        let mut get_bool = |name: &str| {
+            opts.remove(name)
+                .map(|v| parse_bool(&v).unwrap_or_else(|| error!("invalid value for option \"{name}\": \"{v}\"")))
+                .unwrap_or(false)
+        };
+        let allow_http = get_bool("allow_http");
+        let virtual_hosted_style = get_bool("virtual_hosted_style");
+        let use_environment_credentials = get_bool("use_environment_credentials");
+
+        Self {
+            endpoint: opts.remove("endpoint"),
+            region: opts.remove("region"),
+            catalog_root: opts.remove("catalog_root"),
+            bucket: opts.remove("bucket"),
+            allow_http,
+            virtual_hosted_style,
+            use_environment_credentials,
+            df_settings: take_datafusion_settings(&mut opts),
+        }
+    }
+}
+
+// object store credentials, read from pg_user_mapping
+#[derive(Debug, Clone, Default)]
+pub struct UserMappingOptions {
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    pub session_token: Option<String>,
+}
+
+impl UserMappingOptions {
+    // credentials for the server's object store, local files are read without
+    // a user mapping
+    pub unsafe fn for_server(serverid: pg_sys::Oid, server_options: &ServerOptions) -> Self {
+        match server_options.bucket {
+            Some(_) => Self::from_user_mapping(serverid),
+            None => Self::default(),
+        }
+    }
+
+    // mapping of the current user, or the PUBLIC mapping if the user has none,
+    // GetUserMapping raises an error when there is neither
+    pub unsafe fn from_user_mapping(serverid: pg_sys::Oid) -> Self {
+        let user_mapping = pg_sys::GetUserMapping(pg_sys::GetUserId(), serverid);
+        let mut opts = options_to_hashmap((*user_mapping).options);
+
+        Self {
+            access_key_id: opts.remove("access_key_id"),
+            secret_access_key: opts.remove("secret_access_key"),
+            session_token: opts.remove("session_token"),
+        }
+    }
+}
+
+// options of a foreign table, read from pg_foreign_table
+#[derive(Debug, Clone)]
+pub struct TableOptions {
+    pub server_id: pg_sys::Oid,
+    // file or directory, local or object store url, defaults to the dataset
+    // of table_name and schema_name under the server's catalog_root
+    pub location: String,
+    pub format: DataFormat,
+    // name of the table in the DataFusion catalog, defaults to the relation name
+    pub table_name: String,
+    // DataFusion schema of the table, defaults to the catalog's default schema
+    pub schema_name: Option<String>,
+    // datafusion.* configuration keys, applied after the server's
+    pub df_settings: Vec<(String, String)>,
+}
+
+impl TableOptions {
+    pub unsafe fn from_foreign_table(relid: pg_sys::Oid) -> Self {
+        let table = pg_sys::GetForeignTable(relid);
+        let mut opts = options_to_hashmap((*table).options);
+
+        let location = opts.remove("location").or_else(|| opts.remove("path"));
+
+        let format = match opts.remove("format") {
+            Some(v) => DataFormat::from_str(&v).unwrap_or_else(|e| error!("{e}")),
+            None => DataFormat::Parquet,
+        };
+
+        let table_name = match opts.remove("table_name") {
+            Some(v) => v,
+            None => CStr::from_ptr(pg_sys::get_rel_name(relid))
+                .to_str()
+                .unwrap()
+                .to_owned(),
+        };
+
+        let schema_name = opts.remove("schema_name");
+        let df_settings = take_datafusion_settings(&mut opts);
+
+        let location = match location {
+            Some(v) => v,
+            None => {
+                let server_options = ServerOptions::from_foreign_server((*table).serverid);
+                let Some(root) = server_options.catalog_root else {
+                    ErrorReport::new(
+                        PgSqlErrorCode::ERRCODE_FDW_OPTION_NAME_NOT_FOUND,
+                        "foreign table has no \"location\" option",
+                        pg_sys::function_name!(),
+                    )
+                    .set_hint("Set \"location\" on the table or \"catalog_root\" on its server.")
+                    .report(PgLogLevel::ERROR);
+                    unreachable!()
+                };
+                // the relation name stands in for a missing table_name and
+                // isn't validated
+                let names = [("table_name", Some(table_name.as_str())), ("schema_name", schema_name.as_deref())];
+                for (key, value) in names {
+                    let Some(value) = value else { continue };
+                    if let Err(reason) = check_catalog_name(value) {
+                        ErrorReport::new(
+                            PgSqlErrorCode::ERRCODE_FDW_INVALID_ATTRIBUTE_VALUE,
+                            format!("invalid value for option \"{key}\": \"{value}\""),
+                            pg_sys::function_name!(),
+                        )
+                        .set_hint(reason)
+                        .report(PgLogLevel::ERROR)
+                    }
+                }
+                catalog_location(&root, schema_name.as_deref(), &table_name)
+            }
+        };
+
+        Self {
+            server_id: (*table).serverid,
+            location,
+            format,
+            table_name,
+            schema_name,
+            df_settings,
+        }
+    }
+}
+
+// directory of a table under a catalog root: <root>/<schema>/<table>/, or
+// <root>/<table>/ for tables in the default schema
+fn catalog_location(root: &str, schema_name: Option<&str>, table_name: &str) -> String {
+    let root = root.trim_end_matches('/');
+    match schema_name {
+        Some(schema) => format!("{root}/{schema}/{table_name}/"),
+        None => format!("{root}/{table_name}/"),
+    }
+}
+
+impl std::fmt::Display for TableOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.schema_name {
+            Some(schema) => write!(f, "\"{}\".\"{}\"", schema, self.table_name),
+            None => write!(f, "\"{}\"", self.table_name),
+        }
+    }
+}
+
+// remove the datafusion.* entries from a set of options
+fn take_datafusion_settings(opts: &mut HashMap<String, String>) -> Vec<(String, String)> {
+    let keys = opts
+        .keys()
+        .filter(|k| k.starts_with(DATAFUSION_CONFIG_PREFIX))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    keys.into_iter()
+        .map(|k| {
+            let v = opts.remove(&k).unwrap();
+            (k, v)
+        })
+        .collect()
+}
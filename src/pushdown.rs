@@ -0,0 +1,937 @@
+use std::cell::RefCell;
+use std::ffi::{c_void, CStr};
+use std::str::FromStr;
+
+use datafusion::common::{DFSchema, ScalarValue};
+use datafusion::arrow::datatypes::{DataType, TimeUnit};
+use datafusion::logical_expr::expr::{Case, Like, Placeholder, ScalarFunction};
+use datafusion::logical_expr::{binary_expr, cast, ident, lit, BuiltinScalarFunction, Expr, Operator};
+use pgrx::prelude::*;
+use pgrx::{is_a, FromDatum, PgList};
+
+use crate::convert::{is_lossless_cast, pg_to_arrow_type};
+use crate::options::remote_column_name;
+use crate::utils::{datum_to_text, is_compatible, POSTGRES_EPOCH_OFFSET_DAYS, POSTGRES_EPOCH_OFFSET_MICROS};
+
+// oids below this are assigned to built-in objects, see FirstGenbkiObjectId in access/transam.h
+const FIRST_GENBKI_OBJECT_ID: u32 = 10000;
+
+// what deparsing needs to know about the scanned foreign table
+pub struct DeparseContext<'a> {
+    // range table index of the foreign table
+    pub relid: u32,
+    pub foreigntableid: pg_sys::Oid,
+    // schema of the DataFusion table
+    pub schema: &'a DFSchema,
+    // the value CaseTestExpr stands for inside a CASE with an argument
+    pub case_test: Option<Expr>,
+    // expressions evaluated by the executor, placeholder $n is params[n - 1]
+    pub params: &'a RefCell<Vec<*mut pg_sys::Node>>,
+}
+
+fn is_builtin(oid: pg_sys::Oid) -> bool {
+    oid.as_u32() < FIRST_GENBKI_OBJECT_ID
+}
+
+// split the restriction clauses of a foreign table into those DataFusion can
+// evaluate, returned with their translation, and those left to Postgres
+pub unsafe fn classify_conditions(
+    ctx: &DeparseContext,
+    conditions: *mut pg_sys::List,
+) -> (Vec<Expr>, Vec<*mut pg_sys::RestrictInfo>, Vec<*mut pg_sys::RestrictInfo>) {
+    let mut remote_filters = vec![];
+    let mut remote_conds = vec![];
+    let mut local_conds = vec![];
+
+    let conditions: PgList<pg_sys::RestrictInfo> = PgList::from_pg(conditions);
+    for rinfo in conditions.iter_ptr() {
+        let clause = (*rinfo).clause as *mut pg_sys::Node;
+        match deparse_expr(ctx, clause) {
+            Some(expr) => {
+                remote_filters.push(expr);
+                remote_conds.push(rinfo);
+            }
+            None => {
+                // ship what can be shipped, the whole clause is rechecked locally
+                if let Some(expr) = deparse_implied(ctx, clause) {
+                    remote_filters.push(expr);
+                }
+                local_conds.push(rinfo);
+            }
+        }
+    }
+
+    (remote_filters, remote_conds, local_conds)
+}
+
+// run a deparse step, forgetting the parameters it collected when it fails
+unsafe fn rollback_params(ctx: &DeparseContext, f: impl FnOnce() -> Option<Expr>) -> Option<Expr> {
+    let nparams = ctx.params.borrow().len();
+    let expr = f();
+    if expr.is_none() {
+        ctx.params.borrow_mut().truncate(nparams);
+    }
+    expr
+}
+
+// translate a Postgres expression into a DataFusion expression with the same
+// semantics, None if it can't be shipped
+pub unsafe fn deparse_expr(ctx: &DeparseContext, node: *mut pg_sys::Node) -> Option<Expr> {
+    rollback_params(ctx, || deparse_node(ctx, node))
+}
+
+unsafe fn deparse_node(ctx: &DeparseContext, node: *mut pg_sys::Node) -> Option<Expr> {
+    if node.is_null() {
+        return None;
+    }
+
+    if is_a(node, pg_sys::NodeTag::T_Var) {
+        deparse_var(ctx, node as *mut pg_sys::Var)
+    } else if is_a(node, pg_sys::NodeTag::T_Const) {
+        deparse_const(node as *mut pg_sys::Const)
+    } else if is_a(node, pg_sys::NodeTag::T_Param) {
+        let param = node as *mut pg_sys::Param;
+        match (*param).paramkind {
+            pg_sys::ParamKind_PARAM_EXTERN | pg_sys::ParamKind_PARAM_EXEC => {
+                deparse_param(ctx, node, (*param).paramtype)
+            }
+            _ => None,
+        }
+    } else if is_a(node, pg_sys::NodeTag::T_OpExpr) {
+        deparse_op_expr(ctx, node as *mut pg_sys::OpExpr)
+    } else if is_a(node, pg_sys::NodeTag::T_ScalarArrayOpExpr) {
+        deparse_scalar_array_op_expr(ctx, node as *mut pg_sys::ScalarArrayOpExpr)
+    } else if is_a(node, pg_sys::NodeTag::T_NullTest) {
+        deparse_null_test(ctx, node as *mut pg_sys::NullTest)
+    } else if is_a(node, pg_sys::NodeTag::T_BoolExpr) {
+        deparse_bool_expr(ctx, node as *mut pg_sys::BoolExpr)
+    } else if is_a(node, pg_sys::NodeTag::T_FuncExpr) {
+        deparse_func_expr(ctx, node as *mut pg_sys::FuncExpr)
+    } else if is_a(node, pg_sys::NodeTag::T_RelabelType) {
+        // binary compatible, e.g. varchar to text
+        deparse_expr(ctx, (*(node as *mut pg_sys::RelabelType)).arg as *mut pg_sys::Node)
+    } else if is_a(node, pg_sys::NodeTag::T_CoerceViaIO) {
+        let coerce = node as *mut pg_sys::CoerceViaIO;
+        deparse_cast(ctx, (*coerce).arg as *mut pg_sys::Node, (*coerce).resulttype)
+    } else if is_a(node, pg_sys::NodeTag::T_CaseExpr) {
+        deparse_case_expr(ctx, node as *mut pg_sys::CaseExpr)
+    } else if is_a(node, pg_sys::NodeTag::T_CaseTestExpr) {
+        ctx.case_test.clone()
+    } else {
+        None
+    }
+}
+
+// a condition implied by a clause that can't be shipped as a whole, e.g.
+// "a = 1" for "a = 1 AND f(b)" or "a = 1 OR a = 2" for
+// "(a = 1 AND f(b)) OR (a = 2 AND g(b))"
+unsafe fn deparse_implied(ctx: &DeparseContext, node: *mut pg_sys::Node) -> Option<Expr> {
+    rollback_params(ctx, || deparse_implied_node(ctx, node))
+}
+
+unsafe fn deparse_implied_node(ctx: &DeparseContext, node: *mut pg_sys::Node) -> Option<Expr> {
+    if let Some(expr) = deparse_expr(ctx, node) {
+        return Some(expr);
+    }
+    if !is_a(node, pg_sys::NodeTag::T_BoolExpr) {
+        return None;
+    }
+
+    let bool_expr = node as *mut pg_sys::BoolExpr;
+    let args: PgList<pg_sys::Node> = PgList::from_pg((*bool_expr).args);
+    match (*bool_expr).boolop {
+        pg_sys::BoolExprType_AND_EXPR => args
+            .iter_ptr()
+            .filter_map(|arg| deparse_implied(ctx, arg))
+            .reduce(Expr::and),
+        pg_sys::BoolExprType_OR_EXPR => args
+            .iter_ptr()
+            .map(|arg| deparse_implied(ctx, arg))
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .reduce(Expr::or),
+        _ => None,
+    }
+}
+
+// a column of the foreign table, only when its values are read without a cast
+unsafe fn deparse_var(ctx: &DeparseContext, var: *mut pg_sys::Var) -> Option<Expr> {
+    if (*var).varlevelsup != 0 {
+        return None;
+    }
+    // a column of another relation, e.g. the outer side of a nested loop, is
+    // a parameter of the scan
+    if (*var).varno as u32 != ctx.relid {
+        return deparse_param(ctx, var as *mut pg_sys::Node, (*var).vartype);
+    }
+    if (*var).varattno <= 0 {
+        return None;
+    }
+
+    let name = remote_column_name(ctx.foreigntableid, (*var).varattno);
+    let field = ctx.schema.field_with_unqualified_name(&name).ok()?;
+    if !is_compatible(field.data_type(), (*var).vartype) {
+        return None;
+    }
+    // a UTC column read as timestamp, or a local one read as timestamptz, is
+    // shifted by the session time zone when converted, DataFusion would see
+    // the unshifted value
+    match (field.data_type(), (*var).vartype) {
+        (DataType::Timestamp(_, None), pg_sys::TIMESTAMPTZOID)
+        | (DataType::Timestamp(_, Some(_)), pg_sys::TIMESTAMPOID) => return None,
+        // literals and parameters are in microseconds, DataFusion would
+        // truncate them to a coarser column unit
+        (DataType::Timestamp(unit, _), _) if *unit != TimeUnit::Microsecond => return None,
+        _ => {}
+    }
+
+    Some(ident(name))
+}
+
+// a value known at execution time only, a placeholder filled in by param_value
+unsafe fn deparse_param(ctx: &DeparseContext, node: *mut pg_sys::Node, type_oid: pg_sys::Oid) -> Option<Expr> {
+    let data_type = scalar_type(type_oid)?;
+
+    let mut params = ctx.params.borrow_mut();
+    params.push(node);
+
+    Some(Expr::Placeholder(Placeholder::new(format!("${}", params.len()), Some(data_type))))
+}
+
+// the type of the values datum_to_scalar returns, for those that don't
+// depend on the value
+fn scalar_type(type_oid: pg_sys::Oid) -> Option<DataType> {
+    match type_oid {
+        pg_sys::BOOLOID => Some(DataType::Boolean),
+        pg_sys::INT2OID => Some(DataType::Int16),
+        pg_sys::INT4OID => Some(DataType::Int32),
+        pg_sys::INT8OID => Some(DataType::Int64),
+        pg_sys::FLOAT4OID => Some(DataType::Float32),
+        pg_sys::FLOAT8OID => Some(DataType::Float64),
+        pg_sys::TEXTOID | pg_sys::VARCHAROID => Some(DataType::Utf8),
+        pg_sys::DATEOID => Some(DataType::Date32),
+        pg_sys::TIMESTAMPOID => Some(DataType::Timestamp(TimeUnit::Microsecond, None)),
+        pg_sys::TIMESTAMPTZOID => Some(DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))),
+        _ => None,
+    }
+}
+
+// the value of an evaluated parameter expression
+pub unsafe fn param_value(datum: pg_sys::Datum, is_null: bool, type_oid: pg_sys::Oid) -> ScalarValue {
+    // deparse_param only accepts these types
+    let data_type = scalar_type(type_oid).unwrap();
+    if is_null {
+        return ScalarValue::try_from(&data_type).unwrap();
+    }
+
+    datum_to_scalar(datum, type_oid).unwrap_or_else(|| error!("parameter value is out of range"))
+}
+
+unsafe fn deparse_const(cst: *mut pg_sys::Const) -> Option<Expr> {
+    if (*cst).constisnull {
+        return None;
+    }
+
+    datum_to_scalar((*cst).constvalue, (*cst).consttype).map(lit)
+}
+
+// convert a Postgres datum to a DataFusion literal value
+pub unsafe fn datum_to_scalar(datum: pg_sys::Datum, type_oid: pg_sys::Oid) -> Option<ScalarValue> {
+    let value = match type_oid {
+        pg_sys::BOOLOID => ScalarValue::Boolean(bool::from_datum(datum, false)),
+        pg_sys::INT2OID => ScalarValue::Int16(i16::from_datum(datum, false)),
+        pg_sys::INT4OID => ScalarValue::Int32(i32::from_datum(datum, false)),
+        pg_sys::INT8OID => ScalarValue::Int64(i64::from_datum(datum, false)),
+        pg_sys::FLOAT4OID => ScalarValue::Float32(f32::from_datum(datum, false)),
+        pg_sys::FLOAT8OID => ScalarValue::Float64(f64::from_datum(datum, false)),
+        // bpchar ignores trailing spaces when comparing, DataFusion doesn't
+        pg_sys::TEXTOID | pg_sys::VARCHAROID => ScalarValue::Utf8(String::from_datum(datum, false)),
+        pg_sys::NUMERICOID => numeric_to_scalar(&datum_to_text(datum, type_oid))?,
+        // -infinity and infinity become the smallest and largest Arrow values,
+        // which sort before and after every other value as in Postgres
+        pg_sys::DATEOID => {
+            let days = match i32::from_datum(datum, false)? {
+                days @ (i32::MIN | i32::MAX) => days,
+                days => i32::try_from(days as i64 + POSTGRES_EPOCH_OFFSET_DAYS).ok()?,
+            };
+            ScalarValue::Date32(Some(days))
+        }
+        pg_sys::TIMESTAMPOID => {
+            ScalarValue::TimestampMicrosecond(Some(timestamp_micros(i64::from_datum(datum, false)?)?), None)
+        }
+        pg_sys::TIMESTAMPTZOID => ScalarValue::TimestampMicrosecond(
+            Some(timestamp_micros(i64::from_datum(datum, false)?)?),
+            Some("UTC".into()),
+        ),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+// microseconds since the Unix epoch of a Postgres timestamp
+fn timestamp_micros(micros: i64) -> Option<i64> {
+    match micros {
+        i64::MIN | i64::MAX => Some(micros),
+        micros => micros.checked_add(POSTGRES_EPOCH_OFFSET_MICROS),
+    }
+}
+
+// a finite numeric as a Decimal128, e.g. "-12.50" is 1250 with scale 2
+fn numeric_to_scalar(text: &str) -> Option<ScalarValue> {
+    let (int_part, frac_part) = text.split_once('.').unwrap_or((text, ""));
+    let digits = format!("{int_part}{frac_part}");
+    let value = digits.parse::<i128>().ok()?;
+
+    let scale = frac_part.len();
+    if digits.trim_start_matches('-').len() > 38 || scale > 38 {
+        return None;
+    }
+
+    Some(ScalarValue::Decimal128(Some(value), 38, scale as i8))
+}
+
+fn comparison_operator(name: &str) -> Option<Operator> {
+    match name {
+        "=" => Some(Operator::Eq),
+        "<>" => Some(Operator::NotEq),
+        "<" => Some(Operator::Lt),
+        "<=" => Some(Operator::LtEq),
+        ">" => Some(Operator::Gt),
+        ">=" => Some(Operator::GtEq),
+        _ => None,
+    }
+}
+
+// the C collation folds only ASCII letters, DataFusion all of Unicode
+unsafe fn case_folding_is_shippable(collid: pg_sys::Oid) -> bool {
+    collid == pg_sys::InvalidOid
+        || (pg_sys::get_collation_isdeterministic(collid) && !pg_sys::lc_collate_is_c(collid))
+}
+
+// equality is the same as DataFusion's for deterministic collations, ordering
+// only for the C collation which compares bytes
+unsafe fn collation_is_shippable(collid: pg_sys::Oid, op: Operator) -> bool {
+    if collid == pg_sys::InvalidOid {
+        return true;
+    }
+
+    match op {
+        Operator::Eq | Operator::NotEq => pg_sys::get_collation_isdeterministic(collid),
+        _ => pg_sys::lc_collate_is_c(collid),
+    }
+}
+
+// the DataFusion operator of a built-in comparison when it behaves the same
+unsafe fn shippable_comparison(
+    opno: pg_sys::Oid,
+    inputcollid: pg_sys::Oid,
+    left_type: pg_sys::Oid,
+    right_type: pg_sys::Oid,
+) -> Option<Operator> {
+    if !is_builtin(opno) {
+        return None;
+    }
+
+    let opname = CStr::from_ptr(pg_sys::get_opname(opno)).to_str().ok()?;
+    let operator = comparison_operator(opname)?;
+    if !collation_is_shippable(inputcollid, operator) {
+        return None;
+    }
+
+    // enums, intervals, jsonb, arrays and composites don't order or compare
+    // the same way in DataFusion, floats are compared by IEEE total order
+    // where -0.0 is less than 0.0
+    let scalar = |t| {
+        (scalar_type(t).is_some() || t == pg_sys::NUMERICOID)
+            && !matches!(t, pg_sys::FLOAT4OID | pg_sys::FLOAT8OID)
+    };
+    if !scalar(left_type) || !scalar(right_type) {
+        return None;
+    }
+
+    // comparing timestamptz with timestamp or date depends on the session time zone
+    if left_type != right_type
+        && (left_type == pg_sys::TIMESTAMPTZOID || right_type == pg_sys::TIMESTAMPTZOID)
+    {
+        return None;
+    }
+
+    Some(operator)
+}
+
+unsafe fn deparse_op_expr(ctx: &DeparseContext, op: *mut pg_sys::OpExpr) -> Option<Expr> {
+    let args: PgList<pg_sys::Node> = PgList::from_pg((*op).args);
+
+    if let Some(matcher) = pattern_operator((*op).opno) {
+        return deparse_pattern_match(ctx, op, &args, matcher);
+    }
+    if let Some(operator) = arithmetic_operator((*op).opno) {
+        return deparse_arithmetic(ctx, &args, operator);
+    }
+
+    if args.len() != 2 {
+        return None;
+    }
+
+    let operator = shippable_comparison(
+        (*op).opno,
+        (*op).inputcollid,
+        pg_sys::exprType(args.get_ptr(0)?),
+        pg_sys::exprType(args.get_ptr(1)?),
+    )?;
+
+    let left = deparse_expr(ctx, args.get_ptr(0)?)?;
+    let right = deparse_expr(ctx, args.get_ptr(1)?)?;
+
+    Some(binary_expr(left, operator, right))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PatternMatch {
+    regex: bool,
+    negated: bool,
+    case_insensitive: bool,
+}
+
+// the built-in LIKE and regular expression operators on text
+unsafe fn pattern_operator(opno: pg_sys::Oid) -> Option<PatternMatch> {
+    if !is_builtin(opno) {
+        return None;
+    }
+
+    let opname = CStr::from_ptr(pg_sys::get_opname(opno)).to_str().ok()?;
+    let (regex, negated, case_insensitive) = match opname {
+        "~~" => (false, false, false),
+        "!~~" => (false, true, false),
+        "~~*" => (false, false, true),
+        "!~~*" => (false, true, true),
+        "~" => (true, false, false),
+        "!~" => (true, true, false),
+        "~*" => (true, false, true),
+        "!~*" => (true, true, true),
+        _ => return None,
+    };
+
+    Some(PatternMatch {
+        regex,
+        negated,
+        case_insensitive,
+    })
+}
+
+// matching with a constant pattern, when DataFusion's LIKE or Rust regex
+// syntax means the same as the Postgres pattern
+unsafe fn deparse_pattern_match(
+    ctx: &DeparseContext,
+    op: *mut pg_sys::OpExpr,
+    args: &PgList<pg_sys::Node>,
+    matcher: PatternMatch,
+) -> Option<Expr> {
+    if args.len() != 2 {
+        return None;
+    }
+
+    let (arg, pattern) = (args.get_ptr(0)?, args.get_ptr(1)?);
+    if pg_sys::exprType(arg) != pg_sys::TEXTOID || !is_a(pattern, pg_sys::NodeTag::T_Const) {
+        return None;
+    }
+
+    let pattern = pattern as *mut pg_sys::Const;
+    if (*pattern).constisnull || (*pattern).consttype != pg_sys::TEXTOID {
+        return None;
+    }
+    let pattern = String::from_datum((*pattern).constvalue, false)?;
+
+    // backslash escapes differ, e.g. LIKE's default escape or \m and \y in
+    // Postgres regular expressions
+    if pattern.contains('\\') {
+        return None;
+    }
+
+    let collid = (*op).inputcollid;
+    if collid != pg_sys::InvalidOid && !pg_sys::get_collation_isdeterministic(collid) {
+        return None;
+    }
+    if matcher.case_insensitive && !case_folding_is_shippable(collid) {
+        return None;
+    }
+
+    let arg = deparse_expr(ctx, arg)?;
+
+    if !matcher.regex {
+        return Some(Expr::Like(Like::new(
+            matcher.negated,
+            Box::new(arg),
+            Box::new(lit(pattern)),
+            None,
+            matcher.case_insensitive,
+        )));
+    }
+
+    // embedded options, word boundaries and collating elements have no Rust
+    // counterpart
+    if ["(?", "***", "[[:<:]]", "[[:>:]]", "[[.", "[[="]
+        .iter()
+        .any(|s| pattern.contains(s))
+    {
+        return None;
+    }
+    // character classes are ASCII only in Rust, as in the C collation
+    if pattern.contains("[:") && !(collid != pg_sys::InvalidOid && pg_sys::lc_collate_is_c(collid)) {
+        return None;
+    }
+    if !brackets_are_shippable(&pattern) {
+        return None;
+    }
+
+    let operator = match (matcher.negated, matcher.case_insensitive) {
+        (false, false) => Operator::RegexMatch,
+        (false, true) => Operator::RegexIMatch,
+        (true, false) => Operator::RegexNotMatch,
+        (true, true) => Operator::RegexNotIMatch,
+    };
+
+    // "." matches a newline in Postgres
+    Some(binary_expr(arg, operator, lit(format!("(?s){pattern}"))))
+}
+
+// inside a bracket expression Rust reads [ as a nested class and &&, -- and
+// ~~ as set operations, Postgres reads them literally
+fn brackets_are_shippable(pattern: &str) -> bool {
+    let mut rest = pattern;
+    while let Some(start) = rest.find('[') {
+        let class = &rest[start + 1..];
+        let class = class.strip_prefix('^').unwrap_or(class);
+        // a leading ] is literal in Postgres
+        if class.starts_with(']') {
+            return false;
+        }
+
+        let mut end = None;
+        let mut i = 0;
+        while let Some(c) = class[i..].chars().next() {
+            let tail = &class[i..];
+            if let Some(name) = tail.strip_prefix("[:") {
+                match name.find(":]") {
+                    Some(len) => {
+                        i += len + 4;
+                        continue;
+                    }
+                    None => return false,
+                }
+            }
+            if c == '[' || tail.starts_with("&&") || tail.starts_with("--") || tail.starts_with("~~") {
+                return false;
+            }
+            if c == ']' {
+                end = Some(i);
+                break;
+            }
+            i += c.len_utf8();
+        }
+
+        let Some(end) = end else {
+            return false;
+        };
+        rest = &class[end + 1..];
+    }
+    true
+}
+
+fn is_integer_type(type_oid: pg_sys::Oid) -> bool {
+    matches!(type_oid, pg_sys::INT2OID | pg_sys::INT4OID)
+}
+
+unsafe fn is_nonzero_const(node: *mut pg_sys::Node) -> bool {
+    if !is_a(node, pg_sys::NodeTag::T_Const) {
+        return false;
+    }
+    let node = node as *mut pg_sys::Const;
+    if (*node).constisnull {
+        return false;
+    }
+
+    match (*node).consttype {
+        pg_sys::INT2OID => matches!(i16::from_datum((*node).constvalue, false), Some(v) if v != 0),
+        pg_sys::INT4OID => matches!(i32::from_datum((*node).constvalue, false), Some(v) if v != 0),
+        _ => false,
+    }
+}
+
+// the built-in arithmetic operators
+unsafe fn arithmetic_operator(opno: pg_sys::Oid) -> Option<Operator> {
+    if !is_builtin(opno) {
+        return None;
+    }
+
+    match CStr::from_ptr(pg_sys::get_opname(opno)).to_str().ok()? {
+        "+" => Some(Operator::Plus),
+        "-" => Some(Operator::Minus),
+        "*" => Some(Operator::Multiply),
+        "/" => Some(Operator::Divide),
+        "%" => Some(Operator::Modulo),
+        _ => None,
+    }
+}
+
+// arithmetic on numbers, where it gives the same result: int2 and int4 are
+// computed as int8 since DataFusion wraps around on overflow where Postgres
+// raises an error, int8 itself isn't shipped for the same reason
+unsafe fn deparse_arithmetic(
+    ctx: &DeparseContext,
+    args: &PgList<pg_sys::Node>,
+    operator: Operator,
+) -> Option<Expr> {
+    let types: Vec<pg_sys::Oid> = args.iter_ptr().map(|arg| pg_sys::exprType(arg)).collect();
+
+    let integers = types.iter().all(|t| is_integer_type(*t));
+    let shippable = if integers {
+        // DataFusion evaluates the whole batch, a zero divisor in a row that
+        // another condition filters out would fail the query
+        !matches!(operator, Operator::Divide | Operator::Modulo) || is_nonzero_const(args.get_ptr(1)?)
+    } else if types.iter().all(|t| matches!(*t, pg_sys::FLOAT4OID | pg_sys::FLOAT8OID)) {
+        // division by zero is an error in Postgres, infinity in DataFusion
+        matches!(operator, Operator::Plus | Operator::Minus | Operator::Multiply)
+    } else if types.iter().all(|t| *t == pg_sys::NUMERICOID) {
+        // the scale of numeric products and quotients differs
+        matches!(operator, Operator::Plus | Operator::Minus)
+    } else {
+        false
+    };
+    if !shippable {
+        return None;
+    }
+
+    let mut exprs = args
+        .iter_ptr()
+        .map(|arg| deparse_expr(ctx, arg))
+        .collect::<Option<Vec<_>>>()?;
+    if integers {
+        exprs = exprs.into_iter().map(|e| cast(e, DataType::Int64)).collect();
+    }
+
+    let mut exprs = exprs.into_iter();
+    match (exprs.next(), exprs.next(), exprs.next()) {
+        (Some(arg), None, None) if operator == Operator::Minus => Some(Expr::Negative(Box::new(arg))),
+        (Some(left), Some(right), None) => Some(binary_expr(left, operator, right)),
+        _ => None,
+    }
+}
+
+// Postgres functions DataFusion has a built-in with the same semantics for,
+// by argument types
+static SHIPPABLE_FUNCTIONS: &[(&str, &[pg_sys::Oid], &str)] = &[
+    ("lower", &[pg_sys::TEXTOID], "lower"),
+    ("upper", &[pg_sys::TEXTOID], "upper"),
+    ("length", &[pg_sys::TEXTOID], "character_length"),
+    ("char_length", &[pg_sys::TEXTOID], "character_length"),
+    ("character_length", &[pg_sys::TEXTOID], "character_length"),
+    ("btrim", &[pg_sys::TEXTOID], "btrim"),
+    ("ltrim", &[pg_sys::TEXTOID], "ltrim"),
+    ("rtrim", &[pg_sys::TEXTOID], "rtrim"),
+    ("substr", &[pg_sys::TEXTOID, pg_sys::INT4OID], "substr"),
+    ("substr", &[pg_sys::TEXTOID, pg_sys::INT4OID, pg_sys::INT4OID], "substr"),
+    ("substring", &[pg_sys::TEXTOID, pg_sys::INT4OID], "substr"),
+    ("substring", &[pg_sys::TEXTOID, pg_sys::INT4OID, pg_sys::INT4OID], "substr"),
+    ("replace", &[pg_sys::TEXTOID, pg_sys::TEXTOID, pg_sys::TEXTOID], "replace"),
+    ("starts_with", &[pg_sys::TEXTOID, pg_sys::TEXTOID], "starts_with"),
+    ("strpos", &[pg_sys::TEXTOID, pg_sys::TEXTOID], "strpos"),
+    ("abs", &[pg_sys::FLOAT4OID], "abs"),
+    ("abs", &[pg_sys::FLOAT8OID], "abs"),
+    ("ceil", &[pg_sys::FLOAT8OID], "ceil"),
+    ("ceiling", &[pg_sys::FLOAT8OID], "ceil"),
+    ("floor", &[pg_sys::FLOAT8OID], "floor"),
+    ("date_trunc", &[pg_sys::TEXTOID, pg_sys::TIMESTAMPOID], "date_trunc"),
+    ("date_part", &[pg_sys::TEXTOID, pg_sys::TIMESTAMPOID], "date_part"),
+    ("extract", &[pg_sys::TEXTOID, pg_sys::TIMESTAMPOID], "date_part"),
+    ("extract", &[pg_sys::TEXTOID, pg_sys::DATEOID], "date_part"),
+];
+
+// fields both sides truncate to the same way
+const DATE_TRUNC_FIELDS: &[&str] = &[
+    "year", "quarter", "month", "week", "day", "hour", "minute", "second", "millisecond", "microsecond",
+];
+
+// integral fields, fractional seconds and epochs are computed differently
+const DATE_PART_FIELDS: &[&str] = &["year", "quarter", "month", "week", "day", "hour", "minute", "dow", "doy"];
+
+unsafe fn deparse_func_expr(ctx: &DeparseContext, func: *mut pg_sys::FuncExpr) -> Option<Expr> {
+    if !is_builtin((*func).funcid) {
+        return None;
+    }
+
+    let args: PgList<pg_sys::Node> = PgList::from_pg((*func).args);
+
+    if matches!(
+        (*func).funcformat,
+        pg_sys::CoercionForm_COERCE_EXPLICIT_CAST | pg_sys::CoercionForm_COERCE_IMPLICIT_CAST
+    ) {
+        // length coercions such as varchar(n) take the typmod as further arguments
+        if args.len() != 1 {
+            return None;
+        }
+        return deparse_cast(ctx, args.get_ptr(0)?, (*func).funcresulttype);
+    }
+
+    let name = CStr::from_ptr(pg_sys::get_func_name((*func).funcid)).to_str().ok()?;
+    let arg_types: Vec<pg_sys::Oid> = args.iter_ptr().map(|arg| pg_sys::exprType(arg)).collect();
+    let (_, _, df_name) = SHIPPABLE_FUNCTIONS
+        .iter()
+        .find(|(pg_name, types, _)| *pg_name == name && *types == arg_types.as_slice())?;
+
+    if matches!(name, "lower" | "upper") && !case_folding_is_shippable((*func).inputcollid) {
+        return None;
+    }
+
+    let mut exprs = args
+        .iter_ptr()
+        .map(|arg| deparse_expr(ctx, arg))
+        .collect::<Option<Vec<_>>>()?;
+
+    // the field must be a constant DataFusion knows, Postgres ignores its case,
+    // the timestamp is tz-naive on both sides as deparse_var keeps others local
+    let fields = match *df_name {
+        "date_trunc" => Some(DATE_TRUNC_FIELDS),
+        "date_part" => Some(DATE_PART_FIELDS),
+        _ => None,
+    };
+    if let Some(fields) = fields {
+        let field = match &exprs[0] {
+            Expr::Literal(ScalarValue::Utf8(Some(field))) => field.to_lowercase(),
+            _ => return None,
+        };
+        if !fields.contains(&field.as_str()) {
+            return None;
+        }
+        exprs[0] = lit(field);
+    }
+
+    let fun = BuiltinScalarFunction::from_str(df_name).ok()?;
+    let expr = Expr::ScalarFunction(ScalarFunction::new(fun, exprs));
+
+    // extract returns numeric, date_part double precision
+    if name == "extract" {
+        return Some(cast(expr, DataType::Decimal128(38, 0)));
+    }
+
+    Some(expr)
+}
+
+// a type cast DataFusion performs without loss and with the same text
+// representation
+unsafe fn deparse_cast(ctx: &DeparseContext, arg: *mut pg_sys::Node, target_type: pg_sys::Oid) -> Option<Expr> {
+    // casts to char(n) pad the value, casts from it trim trailing blanks
+    let source_type = pg_sys::exprType(arg);
+    if target_type == pg_sys::BPCHAROID || source_type == pg_sys::BPCHAROID {
+        return None;
+    }
+
+    let from = pg_to_arrow_type(source_type)?;
+    let to = pg_to_arrow_type(target_type)?;
+    if from != to && !is_lossless_cast(&from, &to) {
+        return None;
+    }
+
+    Some(cast(deparse_expr(ctx, arg)?, to))
+}
+
+unsafe fn deparse_case_expr(ctx: &DeparseContext, case: *mut pg_sys::CaseExpr) -> Option<Expr> {
+    // "CASE arg WHEN value" compares CaseTestExpr, standing for arg, to value
+    let arg_ctx;
+    let ctx = if (*case).arg.is_null() {
+        ctx
+    } else {
+        arg_ctx = DeparseContext {
+            case_test: Some(deparse_expr(ctx, (*case).arg as *mut pg_sys::Node)?),
+            ..*ctx
+        };
+        &arg_ctx
+    };
+
+    let whens: PgList<pg_sys::CaseWhen> = PgList::from_pg((*case).args);
+    let when_then = whens
+        .iter_ptr()
+        .map(|when| {
+            let cond = deparse_expr(ctx, (*when).expr as *mut pg_sys::Node)?;
+            let result = deparse_expr(ctx, (*when).result as *mut pg_sys::Node)?;
+            Some((Box::new(cond), Box::new(result)))
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    // a missing ELSE is a null constant
+    let default = (*case).defresult as *mut pg_sys::Node;
+    let else_expr = if default.is_null()
+        || (is_a(default, pg_sys::NodeTag::T_Const) && (*(default as *mut pg_sys::Const)).constisnull)
+    {
+        None
+    } else {
+        Some(Box::new(deparse_expr(ctx, default)?))
+    };
+
+    Some(Expr::Case(Case::new(None, when_then, else_expr)))
+}
+
+#[pg_guard]
+unsafe extern "C" fn ec_member_is_column(
+    _root: *mut pg_sys::PlannerInfo,
+    rel: *mut pg_sys::RelOptInfo,
+    _ec: *mut pg_sys::EquivalenceClass,
+    em: *mut pg_sys::EquivalenceMember,
+    _arg: *mut c_void,
+) -> bool {
+    let expr = (*em).em_expr as *mut pg_sys::Node;
+    if !is_a(expr, pg_sys::NodeTag::T_Var) {
+        return false;
+    }
+
+    let var = expr as *mut pg_sys::Var;
+    (*var).varno as u32 == (*rel).relid && (*var).varlevelsup == 0
+}
+
+// join clauses that can be evaluated at the scan of a foreign table when the
+// other relations are on the outer side of a nested loop: those from the
+// joininfo list and equalities implied by equivalence classes, e.g.
+// "ft.id = t.id"
+pub unsafe fn join_conditions(
+    root: *mut pg_sys::PlannerInfo,
+    baserel: *mut pg_sys::RelOptInfo,
+) -> Vec<*mut pg_sys::RestrictInfo> {
+    let mut ret = vec![];
+
+    let joininfo: PgList<pg_sys::RestrictInfo> = PgList::from_pg((*baserel).joininfo);
+    for rinfo in joininfo.iter_ptr() {
+        if pg_sys::join_clause_is_movable_to(rinfo, baserel) {
+            ret.push(rinfo);
+        }
+    }
+
+    if (*baserel).has_eclass_joins {
+        let implied: PgList<pg_sys::RestrictInfo> = PgList::from_pg(pg_sys::generate_implied_equalities_for_column(
+            root,
+            baserel,
+            Some(ec_member_is_column),
+            std::ptr::null_mut(),
+            (*baserel).lateral_referencers,
+        ));
+        ret.extend(implied.iter_ptr());
+    }
+
+    ret
+}
+
+// "col op ANY/ALL (array)" with a constant array, an IN list for = ANY and
+// NOT IN for <> ALL
+unsafe fn deparse_scalar_array_op_expr(
+    ctx: &DeparseContext,
+    op: *mut pg_sys::ScalarArrayOpExpr,
+) -> Option<Expr> {
+    let args: PgList<pg_sys::Node> = PgList::from_pg((*op).args);
+    if args.len() != 2 {
+        return None;
+    }
+
+    let array = args.get_ptr(1)?;
+    if !is_a(array, pg_sys::NodeTag::T_Const) {
+        return None;
+    }
+    let array = array as *mut pg_sys::Const;
+    if (*array).constisnull {
+        return None;
+    }
+
+    let elem_type = pg_sys::get_element_type((*array).consttype);
+    if elem_type == pg_sys::InvalidOid {
+        return None;
+    }
+
+    let operator = shippable_comparison(
+        (*op).opno,
+        (*op).inputcollid,
+        pg_sys::exprType(args.get_ptr(0)?),
+        elem_type,
+    )?;
+
+    let left = deparse_expr(ctx, args.get_ptr(0)?)?;
+    let values = array_elements((*array).constvalue, elem_type)?;
+    // an empty array is false for ANY and true for ALL
+    if values.is_empty() {
+        return None;
+    }
+    let values: Vec<Expr> = values.into_iter().map(lit).collect();
+
+    match (operator, (*op).useOr) {
+        (Operator::Eq, true) => Some(left.in_list(values, false)),
+        (Operator::NotEq, false) => Some(left.in_list(values, true)),
+        (_, use_or) => values
+            .into_iter()
+            .map(|v| binary_expr(left.clone(), operator, v))
+            .reduce(if use_or { Expr::or } else { Expr::and }),
+    }
+}
+
+// the elements of a one-dimensional array datum as literal values, None if
+// one of them is null or has no literal counterpart
+unsafe fn array_elements(datum: pg_sys::Datum, elem_type: pg_sys::Oid) -> Option<Vec<ScalarValue>> {
+    let array = pg_sys::pg_detoast_datum(datum.cast_mut_ptr()) as *mut pg_sys::ArrayType;
+    if (*array).ndim > 1 {
+        return None;
+    }
+
+    let mut typlen = 0;
+    let mut typbyval = false;
+    let mut typalign = 0;
+    pg_sys::get_typlenbyvalalign(elem_type, &mut typlen, &mut typbyval, &mut typalign);
+
+    let mut elems: *mut pg_sys::Datum = std::ptr::null_mut();
+    let mut nulls: *mut bool = std::ptr::null_mut();
+    let mut nelems = 0;
+    pg_sys::deconstruct_array(
+        array,
+        elem_type,
+        typlen as i32,
+        typbyval,
+        typalign,
+        &mut elems,
+        &mut nulls,
+        &mut nelems,
+    );
+
+    (0..nelems as usize)
+        .map(|i| {
+            if *nulls.add(i) {
+                return None;
+            }
+            datum_to_scalar(*elems.add(i), elem_type)
+        })
+        .collect()
+}
+
+unsafe fn deparse_null_test(ctx: &DeparseContext, test: *mut pg_sys::NullTest) -> Option<Expr> {
+    // row-wise null tests check every field of a composite
+    if (*test).argisrow {
+        return None;
+    }
+
+    let arg = deparse_expr(ctx, (*test).arg as *mut pg_sys::Node)?;
+    match (*test).nulltesttype {
+        pg_sys::NullTestType_IS_NULL => Some(arg.is_null()),
+        pg_sys::NullTestType_IS_NOT_NULL => Some(arg.is_not_null()),
+        _ => None,
+    }
+}
+
+unsafe fn deparse_bool_expr(ctx: &DeparseContext, bool_expr: *mut pg_sys::BoolExpr) -> Option<Expr> {
+    let args: PgList<pg_sys::Node> = PgList::from_pg((*bool_expr).args);
+    let args = args
+        .iter_ptr()
+        .map(|arg| deparse_expr(ctx, arg))
+        .collect::<Option<Vec<_>>>()?;
+
+    match (*bool_expr).boolop {
+        pg_sys::BoolExprType_AND_EXPR => args.into_iter().reduce(Expr::and),
+        pg_sys::BoolExprType_OR_EXPR => args.into_iter().reduce(Expr::or),
+        pg_sys::BoolExprType_NOT_EXPR => args.into_iter().next().map(|arg| !arg),
+        _ => None,
+    }
+}
@@ -1,81 +1,114 @@
-use std::slice::{Iter, IterMut};
-use std::vec::IntoIter;
-use datafusion::arrow::array::{AsArray, RecordBatch};
-use datafusion::arrow::datatypes::Int32Type;
-use pgrx::IntoDatum;
-use pgrx::pg_sys::Datum;
+use datafusion::arrow::array::RecordBatch;
+use datafusion::arrow::compute::cast;
+use datafusion::arrow::datatypes::DataType;
+use datafusion::arrow::error::ArrowError;
+use pgrx::pg_sys::{Datum, Oid};
+use pgrx::prelude::*;
 
-fn transpose2<T>(v: Vec<Vec<T>>) -> Vec<Vec<T>> {
-    assert!(!v.is_empty());
-    let len = v[0].len();
-    let mut iters: Vec<_> = v.into_iter().map(|n| n.into_iter()).collect();
-    (0..len)
-        .map(|_| {
-            iters
-                .iter_mut()
-                .map(|n| n.next().unwrap())
-                .collect::<Vec<T>>()
-        })
-        .collect()
+use crate::utils::index_to_datum;
+
+// convert one row of a record batch, datums are allocated in the current memory context
+// and None marks a null value
+pub fn recordbatch_row(batch: &RecordBatch, row: usize, types: &[(Oid, i32)]) -> Vec<Option<Datum>> {
+    batch.columns().iter().zip(types).map(|(c, (type_oid, typmod))| {
+        index_to_datum(c, row, *type_oid, *typmod)
+    }
+    ).collect::<Vec<Option<Datum>>>()
 }
 
-pub fn transpose_recordbatch(batch: &RecordBatch) -> Vec<Vec<Datum>> {
-    let ret = batch.columns().iter().map(|c| {
-        c.as_primitive::<Int32Type>().iter().map(|v| v.unwrap_or_default().into_datum().unwrap()).collect()
+// replace dictionary encoded columns, e.g. Dictionary(Int32, Utf8) written by
+// Spark or pandas, with plain arrays of their value type
+pub fn unpack_dictionaries(batch: RecordBatch) -> Result<RecordBatch, ArrowError> {
+    let has_dictionary = batch
+        .columns()
+        .iter()
+        .any(|c| matches!(c.data_type(), DataType::Dictionary(_, _)));
+    if !has_dictionary {
+        return Ok(batch);
+    }
+
+    let schema = batch.schema();
+    let columns = schema.fields().iter().zip(batch.columns()).map(|(f, c)| {
+        match c.data_type() {
+            DataType::Dictionary(_, value_type) => Ok((f.name().clone(), cast(c, value_type)?)),
+            _ => Ok((f.name().clone(), c.clone())),
+        }
     }
-    ).collect::<Vec<Vec<Datum>>>();
-    transpose2::<Datum>(ret)
+    ).collect::<Result<Vec<_>, ArrowError>>()?;
+
+    RecordBatch::try_from_iter(columns)
 }
 
 #[derive(Debug)]
 pub struct DFResult {
-    // df_batches:  Vec<RecordBatch>,
-    // cached_records: Vec<Vec<Datum>>,
-    batch_iter:  IntoIter<RecordBatch>,
-    record_iter:  IntoIter<Vec<Datum>>,
+    batches: Vec<RecordBatch>,
+    next_batch: usize,
+    // batch being returned and the next row in it
+    current_batch: Option<RecordBatch>,
+    current_row: usize,
+    // Postgres type and typmod of each column
+    types: Vec<(Oid, i32)>,
     done: bool,
 }
 
 impl DFResult {
-    pub fn new(mut record_batches: Vec<RecordBatch> ) -> DFResult {
+    pub fn new(record_batches: Vec<RecordBatch>, types: Vec<(Oid, i32)>) -> DFResult {
         let mut ret =
             DFResult {
-            batch_iter: record_batches.into_iter(),
-            record_iter: Default::default(), // default iter returns None
+            batches: record_batches,
+            next_batch: 0,
+            current_batch: None,
+            current_row: 0,
+            types,
                 done: false
         };
         ret.update_cache();
         ret
     }
 
-    pub fn next_record(&mut self) -> Option<Vec<Datum>> {
-        match self.record_iter.next() {
-            None =>{
-                self.update_cache();
-                self.record_iter.next()
-            },
-            Some(v) => {
-                Some(v)
+    // rows are converted one at a time, IterateForeignScan runs in a per-tuple
+    // memory context so by-reference datums can't outlive the returned row
+    pub fn next_record(&mut self) -> Option<Vec<Option<Datum>>> {
+        loop {
+            let batch = self.current_batch.as_ref()?;
+            if self.current_row < batch.num_rows() {
+                let row = recordbatch_row(batch, self.current_row, &self.types);
+                self.current_row += 1;
+                return Some(row);
             }
+            self.update_cache();
         }
-
     }
 
     pub fn finished(self) -> bool {self.done}
+
+    // start over from the first row, the batches are kept for a rescan that
+    // doesn't change the parameters of the query
+    pub fn rewind(&mut self) {
+        self.next_batch = 0;
+        self.current_row = 0;
+        self.done = false;
+        self.update_cache();
+    }
+
     fn update_cache(&mut self) {
         if self.done {
+            self.current_batch = None;
             return;
         }
 
-        match self.batch_iter.next() {
+        match self.batches.get(self.next_batch).cloned() {
             None => {
                 self.done = true;
+                self.current_batch = None;
             }
             Some(batch) => {
-                let cached_records = transpose_recordbatch(&batch);
-                self.record_iter = cached_records.into_iter();
+                let batch = unpack_dictionaries(batch).unwrap_or_else(|e| error!("{e}"));
+                self.current_batch = Some(batch);
+                self.current_row = 0;
+                self.next_batch += 1;
             }
         }
 
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use async_std::task;
+use datafusion::catalog::schema::MemorySchemaProvider;
+use datafusion::catalog::CatalogProvider;
+use datafusion::common::{DataFusionError, OwnedTableReference, TableReference};
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::datasource::listing::{ListingOptions, ListingTableUrl};
+use datafusion::prelude::{SessionConfig, SessionContext};
+use object_store::aws::AmazonS3Builder;
+use url::Url;
+
+use crate::guc;
+use crate::options::{DataFormat, ServerOptions, TableOptions, UserMappingOptions};
+
+pub fn create_session_context(
+    server_options: &ServerOptions,
+    user_options: &UserMappingOptions,
+    df_settings: &[(String, String)],
+) -> datafusion::common::Result<SessionContext> {
+    let mut config =
+        SessionConfig::new()
+            .with_create_default_catalog_and_schema(true)
+            .with_target_partitions(guc::TARGET_PARTITIONS.get() as usize)
+            .with_information_schema(true)
+            .with_parquet_pruning(guc::PARQUET_PRUNING.get())
+            .with_parquet_bloom_filter_pruning(guc::PARQUET_BLOOM_FILTER_PRUNING.get())
+            .with_batch_size(guc::BATCH_SIZE.get() as usize)
+        ;
+
+    // datafusion.* options, checked again since the wrapper may have been
+    // created without the validator
+    for (key, value) in server_options.df_settings.iter().chain(df_settings) {
+        config.options_mut().set(key, value)?;
+    }
+
+    let ctx = SessionContext::new_with_config(config);
+
+    if server_options.bucket.is_some() {
+        register_object_store(&ctx, server_options, user_options)?;
+    }
+
+    Ok(ctx)
+}
+
+// make the server's S3 compatible bucket available as s3://<bucket>
+fn register_object_store(
+    ctx: &SessionContext,
+    server_options: &ServerOptions,
+    user_options: &UserMappingOptions,
+) -> datafusion::common::Result<()> {
+    let bucket_name = server_options.bucket.as_ref().unwrap();
+
+    // only the server options configure the store, AWS_ENDPOINT and the like
+    // are not read from the environment
+    let mut builder = AmazonS3Builder::new()
+        .with_bucket_name(bucket_name)
+        .with_allow_http(server_options.allow_http)
+        .with_virtual_hosted_style_request(server_options.virtual_hosted_style);
+
+    // the region must be set to the region where the bucket exists until the following
+    // issue is resolved
+    // https://github.com/apache/arrow-rs/issues/2795
+    if let Some(region) = &server_options.region {
+        builder = builder.with_region(region);
+    }
+    if let Some(endpoint) = &server_options.endpoint {
+        builder = builder.with_endpoint(endpoint);
+    }
+
+    match &user_options.access_key_id {
+        Some(access_key_id) => {
+            builder = builder.with_access_key_id(access_key_id);
+            if let Some(secret_access_key) = &user_options.secret_access_key {
+                builder = builder.with_secret_access_key(secret_access_key);
+            }
+            if let Some(session_token) = &user_options.session_token {
+                builder = builder.with_token(session_token);
+            }
+        }
+        // without keys the store falls back to the instance role
+        None if server_options.use_environment_credentials => {
+            if let Ok(access_key_id) = std::env::var("AWS_ACCESS_KEY_ID") {
+                builder = builder.with_access_key_id(access_key_id);
+            }
+            if let Ok(secret_access_key) = std::env::var("AWS_SECRET_ACCESS_KEY") {
+                builder = builder.with_secret_access_key(secret_access_key);
+            }
+            if let Ok(session_token) = std::env::var("AWS_SESSION_TOKEN") {
+                builder = builder.with_token(session_token);
+            }
+        }
+        None => {
+            return Err(DataFusionError::Configuration(format!(
+                "user mapping has no credentials for bucket \"{bucket_name}\", set \"access_key_id\" \
+                 and \"secret_access_key\" or the server option \"use_environment_credentials\""
+            )));
+        }
+    }
+
+    let s3 = builder.build()?;
+
+    let s3_url = Url::parse(&format!("s3://{bucket_name}"))
+        .map_err(|e| DataFusionError::Configuration(e.to_string()))?;
+    ctx.runtime_env()
+        .register_object_store(&s3_url, Arc::new(s3));
+
+    Ok(())
+}
+
+// register the dataset of a foreign table in the session catalog,
+// creating the schema on first use
+pub fn register_foreign_table(
+    ctx: &SessionContext,
+    table_options: &TableOptions,
+) -> datafusion::common::Result<OwnedTableReference> {
+    let table_ref = match &table_options.schema_name {
+        Some(schema) => {
+            let catalog_name = ctx.state().config_options().catalog.default_catalog.clone();
+            let catalog = ctx.catalog(&catalog_name).ok_or_else(|| {
+                DataFusionError::Plan(format!("catalog \"{catalog_name}\" doesn't exist"))
+            })?;
+            if catalog.schema(schema).is_none() {
+                catalog.register_schema(schema, Arc::new(MemorySchemaProvider::new()))?;
+            }
+            TableReference::partial(schema.clone(), table_options.table_name.clone())
+        }
+        None => TableReference::bare(table_options.table_name.clone()),
+    };
+
+    let list_options = ListingOptions::new(table_options.format.file_format())
+        .with_file_extension(table_options.format.file_extension());
+    task::block_on(ctx.register_listing_table(
+        table_ref.clone(),
+        &table_options.location,
+        list_options,
+        None,
+        None,
+    ))?;
+
+    Ok(table_ref)
+}
+
+// a dataset found by IMPORT FOREIGN SCHEMA: a file or a directory of files
+#[derive(Debug, Clone)]
+pub struct Dataset {
+    pub name: String,
+    pub location: String,
+}
+
+// list the datasets directly under a location
+pub fn list_datasets(
+    ctx: &SessionContext,
+    location: &str,
+    format: DataFormat,
+) -> datafusion::common::Result<Vec<Dataset>> {
+    let base = if location.ends_with('/') {
+        location.to_owned()
+    } else {
+        format!("{location}/")
+    };
+
+    let url = ListingTableUrl::parse(&base)?;
+    let store = ctx.runtime_env().object_store(&url)?;
+    let listing = task::block_on(store.list_with_delimiter(Some(url.prefix())))?;
+
+    let mut ret = vec![];
+
+    for dir in listing.common_prefixes {
+        if let Some(name) = dir.filename() {
+            ret.push(Dataset {
+                name: name.to_owned(),
+                location: format!("{base}{name}/"),
+            });
+        }
+    }
+
+    for file in listing.objects {
+        if let Some(file_name) = file.location.filename() {
+            if let Some(name) = file_name.strip_suffix(format.file_extension()) {
+                ret.push(Dataset {
+                    name: name.to_owned(),
+                    location: format!("{base}{file_name}"),
+                });
+            }
+        }
+    }
+
+    Ok(ret)
+}
+
+pub fn infer_schema(
+    ctx: &SessionContext,
+    location: &str,
+    format: DataFormat,
+) -> datafusion::common::Result<SchemaRef> {
+    let url = ListingTableUrl::parse(location)?;
+    let list_options = ListingOptions::new(format.file_format())
+        .with_file_extension(format.file_extension());
+
+    task::block_on(list_options.infer_schema(&ctx.state(), &url))
+}
@@ -1,19 +1,24 @@
-use std::ffi::CStr;
-use std::str::{from_utf8, FromStr};
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::str::FromStr;
 use std::sync::Arc;
 
 use async_std::task;
-use datafusion::arrow::array::{Array, AsArray, BinaryArray, BooleanArray, RecordBatch, StringArray};
-use datafusion::arrow::datatypes::{DataType, Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, TimeUnit, UInt32Type};
+use datafusion::arrow::array::{Array, AsArray, BooleanArray, RecordBatch, StringArray, StructArray};
+use datafusion::arrow::datatypes::{DataType, Fields, Date32Type, Date64Type, Decimal128Type, Decimal256Type, DurationMicrosecondType, DurationMillisecondType, DurationNanosecondType, DurationSecondType, Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, IntervalDayTimeType, IntervalMonthDayNanoType, IntervalUnit, IntervalYearMonthType, Time32MillisecondType, Time32SecondType, Time64MicrosecondType, Time64NanosecondType, TimeUnit, TimestampMicrosecondType, TimestampMillisecondType, TimestampNanosecondType, TimestampSecondType};
+use datafusion::arrow::util::display::{ArrayFormatter, FormatOptions};
 use datafusion::dataframe::DataFrame;
-use datafusion::datasource::file_format::parquet::ParquetFormat;
-use datafusion::datasource::listing::ListingOptions;
-use datafusion::prelude::{ParquetReadOptions, SessionConfig, SessionContext};
-use object_store::aws::AmazonS3Builder;
+use datafusion::common::{DFSchema, DataFusionError};
+use datafusion::logical_expr::{cast, ident, Expr};
 use pgrx::*;
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::prelude::*;
 use pgrx::IntoDatum;
 use pgrx::pg_sys::{Datum, Oid, PgNode};
-use url::Url;
+
+use crate::convert::coerce_column;
+use crate::options::{remote_column_name, ServerOptions, TableOptions, UserMappingOptions};
+use crate::server::{create_session_context, register_foreign_table};
 
 pub(super) trait SerdeList {
     unsafe fn serialize_to_list(state: PgBox<Self>, mut ctx: PgMemoryContexts) -> *mut pg_sys::List
@@ -134,10 +139,376 @@ pub fn generate_test_data_for_oid(oid: Oid) -> Option<Datum> {
 }
 
 
+// text representation of a datum from the output function of its type
+pub unsafe fn datum_to_text(datum: Datum, type_oid: Oid) -> String {
+    let mut typoutput = pg_sys::InvalidOid;
+    let mut typisvarlena = false;
+    pg_sys::getTypeOutputInfo(type_oid, &mut typoutput, &mut typisvarlena);
+
+    let value = pg_sys::OidOutputFunctionCall(typoutput, datum);
+    CStr::from_ptr(value).to_str().unwrap().to_owned()
+}
+
+// build a datum through the input function of a type, which also applies the typmod
+pub unsafe fn datum_from_text(value: &str, type_oid: Oid, typmod: i32) -> Datum {
+    let mut typinput = pg_sys::InvalidOid;
+    let mut typioparam = pg_sys::InvalidOid;
+    pg_sys::getTypeInputInfo(type_oid, &mut typinput, &mut typioparam);
+
+    let value = CString::new(value).unwrap();
+    pg_sys::OidInputFunctionCall(typinput, value.as_ptr() as *mut c_char, typioparam, typmod)
+}
+
+// render an unscaled decimal value, e.g. 12345 with scale 2 is 123.45
+pub fn decimal_to_string(value: String, scale: i8) -> String {
+    let (sign, digits) = match value.strip_prefix('-') {
+        Some(v) => ("-", v),
+        None => ("", value.as_str()),
+    };
+
+    if scale <= 0 {
+        return format!("{sign}{digits}{}", "0".repeat(-(scale as i16) as usize));
+    }
+
+    let scale = scale as usize;
+    let digits = if digits.len() <= scale {
+        format!("{}{digits}", "0".repeat(scale - digits.len() + 1))
+    } else {
+        digits.to_owned()
+    };
+    let (int_part, frac_part) = digits.split_at(digits.len() - scale);
+
+    format!("{sign}{int_part}.{frac_part}")
+}
+
+// microseconds between the Unix epoch and the Postgres epoch 2000-01-01
+pub const POSTGRES_EPOCH_OFFSET_MICROS: i64 = 946_684_800_000_000;
+// range of Postgres timestamps, MIN_TIMESTAMP and END_TIMESTAMP in datatype/timestamp.h
+const MIN_TIMESTAMP: i64 = -211_813_488_000_000_000;
+const END_TIMESTAMP: i64 = 9_223_371_331_200_000_000;
+
+fn timestamp_value(array: &Arc<dyn Array>, index: usize, unit: &TimeUnit) -> i64 {
+    match unit {
+        TimeUnit::Second => array.as_primitive::<TimestampSecondType>().value(index),
+        TimeUnit::Millisecond => array.as_primitive::<TimestampMillisecondType>().value(index),
+        TimeUnit::Microsecond => array.as_primitive::<TimestampMicrosecondType>().value(index),
+        TimeUnit::Nanosecond => array.as_primitive::<TimestampNanosecondType>().value(index),
+    }
+}
+
+// convert an Arrow timestamp since the Unix epoch to a Postgres timestamp, which
+// counts microseconds since 2000-01-01
+pub fn pg_timestamp(value: i64, unit: &TimeUnit) -> i64 {
+    let micros = match unit {
+        TimeUnit::Second => value.checked_mul(1_000_000),
+        TimeUnit::Millisecond => value.checked_mul(1_000),
+        TimeUnit::Microsecond => Some(value),
+        TimeUnit::Nanosecond => Some(value.div_euclid(1_000)),
+    };
+
+    match micros.and_then(|v| v.checked_sub(POSTGRES_EPOCH_OFFSET_MICROS)) {
+        Some(v) if (MIN_TIMESTAMP..END_TIMESTAMP).contains(&v) => v,
+        _ => {
+            ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_DATETIME_VALUE_OUT_OF_RANGE,
+                "timestamp out of range",
+                pg_sys::function_name!(),
+            )
+            .report(PgLogLevel::ERROR);
+            unreachable!()
+        }
+    }
+}
+
+// days between the Unix epoch and the Postgres epoch 2000-01-01
+pub const POSTGRES_EPOCH_OFFSET_DAYS: i64 = 10_957;
+// range of Postgres dates relative to 2000-01-01, see DATE_END_JULIAN in datatype/timestamp.h
+const MIN_DATE: i64 = -2_451_545;
+const END_DATE: i64 = 2_147_483_494 - 2_451_545;
+const MILLIS_PER_DAY: i64 = 86_400_000;
+const MICROS_PER_DAY: i64 = 86_400_000_000;
+
+// convert days since the Unix epoch to a Postgres date
+pub fn pg_date(days: i64) -> i32 {
+    let days = days - POSTGRES_EPOCH_OFFSET_DAYS;
+    if !(MIN_DATE..END_DATE).contains(&days) {
+        ErrorReport::new(
+            PgSqlErrorCode::ERRCODE_DATETIME_VALUE_OUT_OF_RANGE,
+            "date out of range",
+            pg_sys::function_name!(),
+        )
+        .report(PgLogLevel::ERROR);
+    }
+    days as i32
+}
+
+fn time_value(array: &Arc<dyn Array>, index: usize, unit: &TimeUnit) -> i64 {
+    match unit {
+        TimeUnit::Second => array.as_primitive::<Time32SecondType>().value(index) as i64,
+        TimeUnit::Millisecond => array.as_primitive::<Time32MillisecondType>().value(index) as i64,
+        TimeUnit::Microsecond => array.as_primitive::<Time64MicrosecondType>().value(index),
+        TimeUnit::Nanosecond => array.as_primitive::<Time64NanosecondType>().value(index),
+    }
+}
+
+// convert an Arrow time of day to a Postgres time, microseconds since midnight
+pub fn pg_time(value: i64, unit: &TimeUnit) -> i64 {
+    let micros = match unit {
+        TimeUnit::Second => value.checked_mul(1_000_000),
+        TimeUnit::Millisecond => value.checked_mul(1_000),
+        TimeUnit::Microsecond => Some(value),
+        TimeUnit::Nanosecond => Some(value.div_euclid(1_000)),
+    };
+
+    match micros {
+        // 24:00:00 is a valid Postgres time
+        Some(v) if (0..=MICROS_PER_DAY).contains(&v) => v,
+        _ => {
+            ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_DATETIME_VALUE_OUT_OF_RANGE,
+                "time out of range",
+                pg_sys::function_name!(),
+            )
+            .report(PgLogLevel::ERROR);
+            unreachable!()
+        }
+    }
+}
+
+// a duration becomes an interval of microseconds only, like Postgres does for
+// the difference of two timestamps before justify_interval
+fn duration_micros(array: &Arc<dyn Array>, index: usize, unit: &TimeUnit) -> i64 {
+    let micros = match unit {
+        TimeUnit::Second => array.as_primitive::<DurationSecondType>().value(index).checked_mul(1_000_000),
+        TimeUnit::Millisecond => array.as_primitive::<DurationMillisecondType>().value(index).checked_mul(1_000),
+        TimeUnit::Microsecond => Some(array.as_primitive::<DurationMicrosecondType>().value(index)),
+        TimeUnit::Nanosecond => Some(array.as_primitive::<DurationNanosecondType>().value(index) / 1_000),
+    };
+
+    micros.unwrap_or_else(|| {
+        ErrorReport::new(
+            PgSqlErrorCode::ERRCODE_DATETIME_VALUE_OUT_OF_RANGE,
+            "interval out of range",
+            pg_sys::function_name!(),
+        )
+        .report(PgLogLevel::ERROR);
+        unreachable!()
+    })
+}
+
+// text values go to text, varchar(n), bpchar(n) or an enum type; the input
+// function enforces the length of the typmod and the labels of an enum
+pub fn text_to_datum(value: &str, type_oid: Oid, typmod: i32) -> Option<Datum> {
+    if type_oid == pg_sys::TEXTOID || (is_string_type(type_oid) && typmod < 0) {
+        value.into_datum()
+    } else {
+        Some(unsafe { datum_from_text(value, type_oid, typmod) })
+    }
+}
+
+fn is_string_type(type_oid: Oid) -> bool {
+    matches!(type_oid, pg_sys::TEXTOID | pg_sys::VARCHAROID | pg_sys::BPCHAROID)
+}
+
+// build a one-dimensional Postgres array from a list value, the elements are
+// converted to the element type of the array column
+fn list_to_datum(values: &Arc<dyn Array>, array_type: Oid, typmod: i32) -> Option<Datum> {
+    unsafe {
+        let elem_type = pg_sys::get_element_type(array_type);
+        if values.is_empty() {
+            return Some(Datum::from(pg_sys::construct_empty_array(elem_type)));
+        }
+
+        let mut elmlen = 0i16;
+        let mut elmbyval = false;
+        let mut elmalign = 0 as c_char;
+        pg_sys::get_typlenbyvalalign(elem_type, &mut elmlen, &mut elmbyval, &mut elmalign);
+
+        let mut elems = Vec::with_capacity(values.len());
+        let mut nulls = Vec::with_capacity(values.len());
+        for i in 0..values.len() {
+            // the typmod of an array column applies to its elements
+            match index_to_datum(values, i, elem_type, typmod) {
+                Some(v) => {
+                    elems.push(v);
+                    nulls.push(false);
+                }
+                None => {
+                    elems.push(Datum::from(0usize));
+                    nulls.push(true);
+                }
+            }
+        }
+
+        let mut dims = [values.len() as i32];
+        let mut lbs = [1];
+        let array = pg_sys::construct_md_array(
+            elems.as_mut_ptr(),
+            nulls.as_mut_ptr(),
+            1,
+            dims.as_mut_ptr(),
+            lbs.as_mut_ptr(),
+            elem_type,
+            elmlen as i32,
+            elmbyval,
+            elmalign,
+        );
+
+        Some(Datum::from(array))
+    }
+}
+
+fn write_json_string(value: &str, out: &mut String) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn format_value(array: &Arc<dyn Array>, index: usize) -> String {
+    match ArrayFormatter::try_new(array.as_ref(), &FormatOptions::default()) {
+        Ok(f) => f.value(index).to_string(),
+        Err(e) => error!("{e}"),
+    }
+}
+
+// serialize the value at index as JSON text, numbers keep their exact
+// representation since jsonb stores them as numeric
+fn write_json(array: &Arc<dyn Array>, index: usize, out: &mut String) {
+    if array.is_null(index) {
+        out.push_str("null");
+        return;
+    }
+
+    match array.data_type() {
+        DataType::Boolean => out.push_str(if array.as_boolean().value(index) { "true" } else { "false" }),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64
+        | DataType::UInt8 | DataType::UInt16 | DataType::UInt32 | DataType::UInt64
+        | DataType::Decimal128(_, _) | DataType::Decimal256(_, _) => {
+            out.push_str(&format_value(array, index))
+        }
+        DataType::Float16 | DataType::Float32 | DataType::Float64 => {
+            // NaN and infinity have no JSON representation
+            let value = format_value(array, index);
+            match value.parse::<f64>() {
+                Ok(v) if v.is_finite() => out.push_str(&value),
+                _ => out.push_str("null"),
+            }
+        }
+        DataType::Utf8 => write_json_string(array.as_string::<i32>().value(index), out),
+        DataType::LargeUtf8 => write_json_string(array.as_string::<i64>().value(index), out),
+        DataType::Struct(_) => {
+            let array = array.as_struct();
+            out.push('{');
+            for (i, (field, column)) in array.fields().iter().zip(array.columns()).enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json_string(field.name(), out);
+                out.push(':');
+                write_json(column, index, out);
+            }
+            out.push('}');
+        }
+        DataType::Map(_, _) => {
+            let entries = array.as_map().value(index);
+            let (keys, values) = (entries.column(0), entries.column(1));
+            out.push('{');
+            for i in 0..entries.len() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json_string(&format_value(keys, i), out);
+                out.push(':');
+                write_json(values, i, out);
+            }
+            out.push('}');
+        }
+        DataType::List(_) | DataType::LargeList(_) | DataType::FixedSizeList(_, _) => {
+            let values = match array.data_type() {
+                DataType::List(_) => array.as_list::<i32>().value(index),
+                DataType::LargeList(_) => array.as_list::<i64>().value(index),
+                _ => array.as_fixed_size_list().value(index),
+            };
+            out.push('[');
+            for i in 0..values.len() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(&values, i, out);
+            }
+            out.push(']');
+        }
+        // dates, timestamps, binary, ... use the Arrow display format
+        _ => write_json_string(&format_value(array, index), out),
+    }
+}
+
+// build a composite datum from a struct value, attributes are matched to the
+// struct fields by name
+unsafe fn struct_to_composite(array: &StructArray, index: usize, type_oid: Oid, typmod: i32) -> Option<Datum> {
+    let tupdesc = PgTupleDesc::from_pg_unchecked(pg_sys::lookup_rowtype_tupdesc_copy(type_oid, typmod));
+
+    let mut values = vec![Datum::from(0usize); tupdesc.len()];
+    let mut nulls = vec![true; tupdesc.len()];
+
+    for (i, attr) in tupdesc.iter().enumerate() {
+        if attr.attisdropped {
+            continue;
+        }
+        let Some(column) = array.column_by_name(name_data_to_str(&attr.attname)) else {
+            continue;
+        };
+        if let Some(v) = index_to_datum(column, index, attr.atttypid, attr.atttypmod) {
+            values[i] = v;
+            nulls[i] = false;
+        }
+    }
+
+    let tuple = pg_sys::heap_form_tuple(tupdesc.as_ptr(), values.as_mut_ptr(), nulls.as_mut_ptr());
+    Some(pg_sys::heap_copy_tuple_as_datum(tuple, tupdesc.as_ptr()))
+}
+
+// whether every attribute of a composite type has a struct field of the same
+// name and a compatible type
+fn composite_matches(fields: &Fields, type_oid: Oid) -> bool {
+    unsafe {
+        if type_oid == pg_sys::RECORDOID || !pg_sys::type_is_rowtype(type_oid) {
+            return false;
+        }
+
+        let tupdesc = PgTupleDesc::from_pg_unchecked(pg_sys::lookup_rowtype_tupdesc_copy(type_oid, -1));
+        tupdesc.iter().filter(|attr| !attr.attisdropped).all(|attr| {
+            fields
+                .iter()
+                .find(|f| f.name() == name_data_to_str(&attr.attname))
+                .map_or(false, |f| is_compatible_value(f.data_type(), attr.atttypid))
+        })
+    }
+}
+
+// convert the value at index to a datum of the target column type,
+// type_oid and typmod come from the foreign table definition.
+// returns None for a null value
 pub fn index_to_datum(
     array: &Arc<dyn datafusion::arrow::array::Array>,
     index: usize,
+    type_oid: Oid,
+    typmod: i32,
 ) -> Option<Datum> {
+    if array.is_null(index) {
+        return None;
+    }
+
     match array.data_type() {
         DataType::Boolean => array
             .as_any()
@@ -145,22 +516,43 @@ pub fn index_to_datum(
             .unwrap()
             .value(index)
             .into_datum(),
-        DataType::Utf8 => array
-            .as_any()
-            .downcast_ref::<StringArray>()
-            .unwrap()
-            .value(index)
-            .into_datum(),
-        DataType::Binary => from_utf8(array
-            .as_any()
-            .downcast_ref::<BinaryArray>()
-            .unwrap()
-            .value(index)).unwrap_or("none").into_datum(),
+        DataType::Utf8 => text_to_datum(
+            array
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap()
+                .value(index),
+            type_oid,
+            typmod,
+        ),
+        DataType::LargeUtf8 => text_to_datum(array.as_string::<i64>().value(index), type_oid, typmod),
+        DataType::List(_) => list_to_datum(&array.as_list::<i32>().value(index), type_oid, typmod),
+        DataType::LargeList(_) => list_to_datum(&array.as_list::<i64>().value(index), type_oid, typmod),
+        DataType::FixedSizeList(_, _) => {
+            list_to_datum(&array.as_fixed_size_list().value(index), type_oid, typmod)
+        }
+        DataType::Struct(_) if type_oid != pg_sys::JSONBOID => unsafe {
+            struct_to_composite(array.as_struct(), index, type_oid, typmod)
+        },
+        DataType::Struct(_) | DataType::Map(_, _) => {
+            let mut json = String::new();
+            write_json(array, index, &mut json);
+            Some(unsafe { datum_from_text(&json, pg_sys::JSONBOID, -1) })
+        }
+        DataType::Binary => array.as_binary::<i32>().value(index).into_datum(),
+        DataType::LargeBinary => array.as_binary::<i64>().value(index).into_datum(),
+        DataType::FixedSizeBinary(_) => {
+            let value = array.as_fixed_size_binary().value(index);
+            if type_oid == pg_sys::UUIDOID {
+                pgrx::Uuid::from_slice(value).into_datum()
+            } else {
+                value.into_datum()
+            }
+        }
 
         DataType::Int16 => array.as_primitive::<Int16Type>().value(index).into_datum(),
         DataType::Int32 => array.as_primitive::<Int32Type>().value(index).into_datum(),
         DataType::Int64 => array.as_primitive::<Int64Type>().value(index).into_datum(),
-        DataType::UInt32 => array.as_primitive::<UInt32Type>().value(index).into_datum(),
         DataType::Float32 => array
             .as_primitive::<Float32Type>()
             .value(index)
@@ -169,107 +561,315 @@ pub fn index_to_datum(
             .as_primitive::<Float64Type>()
             .value(index)
             .into_datum(),
-        DataType::Decimal128(precision, scale) => None,
-        DataType::Timestamp(TimeUnit::Microsecond, None) => None,
-        DataType::Date32 => None,
-        DataType::Timestamp(TimeUnit::Nanosecond, None) => None,
-        _ => {
-            // unsupported type
-            None
+        // numeric_in checks the value against numeric(p,s) of the column
+        DataType::Decimal128(_, scale) => {
+            let value = array.as_primitive::<Decimal128Type>().value(index);
+            Some(unsafe { datum_from_text(&decimal_to_string(value.to_string(), *scale), type_oid, typmod) })
+        }
+        DataType::Decimal256(_, scale) => {
+            let value = array.as_primitive::<Decimal256Type>().value(index);
+            Some(unsafe { datum_from_text(&decimal_to_string(value.to_string(), *scale), type_oid, typmod) })
+        }
+        DataType::Timestamp(unit, tz) => {
+            let value = pg_timestamp(timestamp_value(array, index, unit), unit).into_datum()?;
+            // zoned values are UTC instants like timestamptz, the others are wall clock
+            // times; converting between them follows the Postgres casts
+            match (tz, type_oid) {
+                (None, pg_sys::TIMESTAMPTZOID) => unsafe {
+                    direct_function_call_as_datum(pg_sys::timestamp_timestamptz, &[Some(value)])
+                },
+                (Some(_), pg_sys::TIMESTAMPOID) => unsafe {
+                    direct_function_call_as_datum(pg_sys::timestamptz_timestamp, &[Some(value)])
+                },
+                _ => Some(value),
+            }
+        }
+        DataType::Date32 => {
+            pg_date(array.as_primitive::<Date32Type>().value(index) as i64).into_datum()
+        }
+        DataType::Date64 => {
+            let millis = array.as_primitive::<Date64Type>().value(index);
+            pg_date(millis.div_euclid(MILLIS_PER_DAY)).into_datum()
+        }
+        DataType::Time32(unit) | DataType::Time64(unit) => {
+            pg_time(time_value(array, index, unit), unit).into_datum()
+        }
+        DataType::Interval(IntervalUnit::YearMonth) => {
+            let months = array.as_primitive::<IntervalYearMonthType>().value(index);
+            pgrx::Interval::new(months, 0, 0).into_datum()
+        }
+        DataType::Interval(IntervalUnit::DayTime) => {
+            let (days, millis) = IntervalDayTimeType::to_parts(
+                array.as_primitive::<IntervalDayTimeType>().value(index),
+            );
+            pgrx::Interval::new(0, days, millis as i64 * 1_000).into_datum()
+        }
+        DataType::Interval(IntervalUnit::MonthDayNano) => {
+            let (months, days, nanos) = IntervalMonthDayNanoType::to_parts(
+                array.as_primitive::<IntervalMonthDayNanoType>().value(index),
+            );
+            pgrx::Interval::new(months, days, nanos / 1_000).into_datum()
+        }
+        DataType::Duration(unit) => {
+            pgrx::Interval::new(0, 0, duration_micros(array, index, unit)).into_datum()
+        }
+        // rejected by check_target_columns at plan time
+        data_type => error!("unsupported Arrow type {data_type}"),
+    }
+}
+
+// Postgres column type for an Arrow field, used by IMPORT FOREIGN SCHEMA
+pub fn arrow_to_pg_type(data_type: &DataType) -> Option<String> {
+    let name = match data_type {
+        DataType::Boolean => "boolean",
+        // integers without a Postgres counterpart are cast by DataFusion
+        DataType::Int8 | DataType::UInt8 | DataType::Int16 => "smallint",
+        DataType::UInt16 | DataType::Int32 => "integer",
+        DataType::UInt32 | DataType::Int64 => "bigint",
+        DataType::UInt64 => "numeric(20,0)",
+        DataType::Float16 | DataType::Float32 => "real",
+        DataType::Float64 => "double precision",
+        DataType::Utf8 | DataType::LargeUtf8 => "text",
+        DataType::Dictionary(_, value_type) => return arrow_to_pg_type(value_type),
+        DataType::Struct(_) | DataType::Map(_, _) => "jsonb",
+        // only one-dimensional arrays are supported
+        DataType::List(field) | DataType::LargeList(field) | DataType::FixedSizeList(field, _) => {
+            return match field.data_type() {
+                DataType::List(_) | DataType::LargeList(_) | DataType::FixedSizeList(_, _) => None,
+                t => arrow_to_pg_type(t).map(|t| format!("{t}[]")),
+            };
+        }
+        DataType::Binary | DataType::LargeBinary | DataType::FixedSizeBinary(_) => "bytea",
+        DataType::Date32 | DataType::Date64 => "date",
+        DataType::Time32(_) | DataType::Time64(_) => "time",
+        DataType::Interval(_) | DataType::Duration(_) => "interval",
+        DataType::Timestamp(_, None) => "timestamp",
+        DataType::Timestamp(_, Some(_)) => "timestamp with time zone",
+        DataType::Decimal128(precision, scale) | DataType::Decimal256(precision, scale) => {
+            return if *scale >= 0 && (*scale as u8) <= *precision {
+                Some(format!("numeric({precision},{scale})"))
+            } else {
+                Some(String::from("numeric"))
+            };
+        }
+        _ => return None,
+    };
+
+    Some(name.to_owned())
+}
+
+// create a session for the foreign table's server and open its dataset
+pub unsafe fn open_foreign_table(table_options: &TableOptions) -> datafusion::common::Result<DataFrame> {
+    let server_options = ServerOptions::from_foreign_server(table_options.server_id);
+    let user_options = UserMappingOptions::for_server(table_options.server_id, &server_options);
+    let ctx = create_session_context(&server_options, &user_options, &table_options.df_settings)?;
+
+    let table_ref = register_foreign_table(&ctx, table_options)?;
+    task::block_on(ctx.table(table_ref))
+}
+
+// build the remote query for a foreign table: scan the registered dataset,
+// filter it and project the referenced columns
+pub unsafe fn run_df_query(
+    table_options: &TableOptions,
+    columns: &[TargetColumn],
+    filters: &[Expr],
+) -> datafusion::common::Result<DataFrame> {
+    let mut df = open_foreign_table(table_options)?;
+
+    // let DataFusion cast the columns whose type differs from the Postgres column
+    let exprs = columns.iter().map(|c| {
+        let field = df.schema().field_with_unqualified_name(&c.name)?;
+        match coerce_column(field.data_type(), c.type_oid) {
+            Ok(None) => Ok(ident(&c.name)),
+            Ok(Some(data_type)) => Ok(cast(ident(&c.name), data_type).alias(&c.name)),
+            Err(e) => Err(DataFusionError::Plan(format!("column \"{}\": {e}", c.name))),
         }
     }
+    ).collect::<datafusion::common::Result<Vec<_>>>()?;
+
+    // filters pushed down from the WHERE clause, applied to the raw columns
+    for filter in filters {
+        df = df.filter(filter.clone())?;
+    }
+
+    df.select(exprs)
 }
 
-pub fn run_df_sql() -> datafusion::common::Result<DataFrame> {
-    let config =
-        SessionConfig::new()
-            .with_create_default_catalog_and_schema(true)
-            .with_target_partitions(8)
-            .with_information_schema(true)
-            .with_parquet_pruning(true)
-            .with_parquet_bloom_filter_pruning(true)
-            .with_batch_size(6666)
-        ;
-
-    let ctx = SessionContext::new_with_config(config);
-
-    // the region must be set to the region where the bucket exists until the following
-    // issue is resolved
-    // https://github.com/apache/arrow-rs/issues/2795
-    let region = "us-east-1";
-    let bucket_name = "data1";
-
-    let s3 = AmazonS3Builder::new()
-        .with_allow_http(true)
-        .with_bucket_name(bucket_name)
-        .with_region(region)
-        .with_access_key_id("minioadmin")
-        .with_secret_access_key("minioadmin")
-        .with_endpoint("http://10.24.11.54:9000")
-        .build()?;
-
-    let path = format!("s3://{bucket_name}");
-    let s3_url = Url::parse(&path).unwrap();
-    ctx.runtime_env()
-        .register_object_store(&s3_url, Arc::new(s3));
-
-    let path = format!("s3://{bucket_name}/");
-    let list_options = ListingOptions::new(Arc::new(ParquetFormat::new())).with_file_extension(".parquet");
-    task::block_on(ctx.register_listing_table("hits", &path, list_options, None, None)
-    ).expect("TODO: panic message");
-
-    // execute the query
-    let df = task::block_on(ctx.sql("SELECT \"RegionID\" from hits order by 1 limit 10;"));
-
-    return df;
+// whether values of an Arrow type can be stored in a column of a Postgres type
+pub fn is_compatible(data_type: &DataType, type_oid: Oid) -> bool {
+    match data_type {
+        // unpack_dictionaries only unpacks top-level columns
+        DataType::Dictionary(_, value_type) => is_compatible_value(value_type, type_oid),
+        _ => is_compatible_value(data_type, type_oid),
+    }
 }
 
-pub fn run_df_sql_local(sql_str: &str) -> datafusion::common::Result<DataFrame> {
-    let config =
-        SessionConfig::new()
-            .with_create_default_catalog_and_schema(true)
-            .with_target_partitions(8)
-            .with_information_schema(true)
-            .with_parquet_pruning(true)
-            .with_parquet_bloom_filter_pruning(true)
-            .with_batch_size(6666)
-        ;
-
-    let ctx = SessionContext::new_with_config(config);
-
-    task::block_on(ctx.register_parquet(
-        "hits",
-        &format!("/home/liyang/hits_10.parquet"),
-        ParquetReadOptions::default(),
-    ));
-
-
-    // execute the query
-    let df = task::block_on(ctx.sql(sql_str));
-    return df;
+fn is_compatible_value(data_type: &DataType, type_oid: Oid) -> bool {
+    match data_type {
+        DataType::Boolean => type_oid == pg_sys::BOOLOID,
+        DataType::Utf8 | DataType::LargeUtf8 => {
+            is_string_type(type_oid) || unsafe { pg_sys::type_is_enum(type_oid) }
+        }
+        DataType::List(field) | DataType::LargeList(field) | DataType::FixedSizeList(field, _) => {
+            let elem_type = unsafe { pg_sys::get_element_type(type_oid) };
+            elem_type != pg_sys::InvalidOid
+                && !matches!(
+                    field.data_type(),
+                    DataType::List(_) | DataType::LargeList(_) | DataType::FixedSizeList(_, _)
+                )
+                && is_compatible_value(field.data_type(), elem_type)
+        }
+        DataType::Struct(fields) => type_oid == pg_sys::JSONBOID || composite_matches(fields, type_oid),
+        DataType::Map(_, _) => type_oid == pg_sys::JSONBOID,
+        DataType::Binary | DataType::LargeBinary => type_oid == pg_sys::BYTEAOID,
+        DataType::FixedSizeBinary(16) => matches!(type_oid, pg_sys::BYTEAOID | pg_sys::UUIDOID),
+        DataType::FixedSizeBinary(_) => type_oid == pg_sys::BYTEAOID,
+        DataType::Int16 => type_oid == pg_sys::INT2OID,
+        DataType::Int32 => type_oid == pg_sys::INT4OID,
+        DataType::Int64 => type_oid == pg_sys::INT8OID,
+        DataType::Float32 => type_oid == pg_sys::FLOAT4OID,
+        DataType::Float64 => type_oid == pg_sys::FLOAT8OID,
+        DataType::Decimal128(_, _) | DataType::Decimal256(_, _) => type_oid == pg_sys::NUMERICOID,
+        DataType::Timestamp(_, _) => matches!(type_oid, pg_sys::TIMESTAMPOID | pg_sys::TIMESTAMPTZOID),
+        DataType::Date32 | DataType::Date64 => type_oid == pg_sys::DATEOID,
+        DataType::Time32(_) | DataType::Time64(_) => type_oid == pg_sys::TIMEOID,
+        DataType::Interval(_) | DataType::Duration(_) => type_oid == pg_sys::INTERVALOID,
+        _ => false,
+    }
 }
 
+// schema of the dataset behind a foreign table
+pub unsafe fn table_schema(table_options: &TableOptions) -> DFSchema {
+    let df = open_foreign_table(table_options).unwrap_or_else(|e| {
+        error!("cannot open foreign table \"{}\": {e}", table_options.table_name)
+    });
+
+    df.schema().clone()
+}
 
-// extract target column name and attribute no list
+// check the referenced columns against the dataset schema, so that a
+// mismatching foreign table definition fails at plan time
+pub unsafe fn check_target_columns(
+    foreigntableid: Oid,
+    table_options: &TableOptions,
+    schema: &DFSchema,
+    columns: &[TargetColumn],
+) {
+    for col in columns {
+        let field = match schema.field_with_unqualified_name(&col.name) {
+            Ok(v) => v,
+            Err(_) => {
+                ErrorReport::new(
+                    PgSqlErrorCode::ERRCODE_UNDEFINED_COLUMN,
+                    format!(
+                        "column \"{}\" does not exist in foreign table {}",
+                        col.name, table_options
+                    ),
+                    pg_sys::function_name!(),
+                )
+                .report(PgLogLevel::ERROR);
+                unreachable!()
+            }
+        };
+
+        let type_oid = pg_sys::get_atttype(foreigntableid, col.attnum);
+        if let Err(reason) = coerce_column(field.data_type(), type_oid) {
+            let type_name = CStr::from_ptr(pg_sys::format_type_be(type_oid)).to_str().unwrap();
+            ErrorReport::new(
+                PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+                format!(
+                    "column \"{}\" of type {} cannot be read into Postgres type {}",
+                    col.name,
+                    field.data_type(),
+                    type_name
+                ),
+                pg_sys::function_name!(),
+            )
+            .set_detail(reason)
+            .report(PgLogLevel::ERROR);
+        }
+    }
+}
+
+
+// a column of the foreign table fetched by the remote query
+#[derive(Debug, Clone)]
+pub struct TargetColumn {
+    pub attnum: i16,
+    // field name in the DataFusion table
+    pub name: String,
+    // type of the Postgres column
+    pub type_oid: Oid,
+    pub typmod: i32,
+}
+
+// extract target column name and attribute no list: the columns in the
+// target list and those needed by the conditions evaluated locally
 pub unsafe fn extract_target_columns(
     root: *mut pg_sys::PlannerInfo,
     baserel: *mut pg_sys::RelOptInfo,
-    foreigntableid: pg_sys::Oid
-) -> Vec<String> {
-
-    //let mut col_vars: *mut pg_sys::List = ptr::null_mut();
-    let mut ret = vec![];
+    foreigntableid: pg_sys::Oid,
+    local_conds: &[*mut pg_sys::RestrictInfo],
+) -> Vec<TargetColumn> {
+
+    let mut ret: Vec<TargetColumn> = vec![];
+
+    // gather vars from target column list, including those inside
+    // PlaceHolderVars evaluated at the scan
+    let flags = pg_sys::PVC_RECURSE_PLACEHOLDERS as i32;
+    let mut vars: Vec<*mut pg_sys::Node> =
+        PgList::<pg_sys::Node>::from_pg(pg_sys::pull_var_clause((*(*baserel).reltarget).exprs as *mut pg_sys::Node, flags))
+            .iter_ptr()
+            .collect();
+    for rinfo in local_conds {
+        let cond_vars: PgList<pg_sys::Node> =
+            PgList::from_pg(pg_sys::pull_var_clause((**rinfo).clause as *mut pg_sys::Node, flags));
+        vars.extend(cond_vars.iter_ptr());
+    }
 
-    // gather vars from target column list
-    let tgt_list: PgList<pg_sys::Node> = PgList::from_pg((*(*baserel).reltarget).exprs);
-    for tgt in tgt_list.iter_ptr() {
+    let mut whole_row = false;
+    for tgt in vars {
+        if !is_a(tgt, pg_sys::NodeTag::T_Var) {
+            continue;
+        }
         let v = tgt as *const pg_sys::Var;
-        let attname = pg_sys::get_attname(foreigntableid, (*v).varattno, false);
+        if (*v).varno as u32 != (*baserel).relid {
+            continue;
+        }
+        // a whole-row reference needs every column
+        if (*v).varattno == 0 {
+            whole_row = true;
+            continue;
+        }
+        // system columns have no remote counterpart
+        if (*v).varattno < 0 || ret.iter().any(|c| c.attnum == (*v).varattno) {
+            continue;
+        }
 
-        ret.push(format!("{}",CStr::from_ptr(attname).to_str().unwrap().to_owned()));
+        ret.push(TargetColumn {
+            attnum: (*v).varattno,
+            name: remote_column_name(foreigntableid, (*v).varattno),
+            type_oid: (*v).vartype,
+            typmod: (*v).vartypmod,
+        });
+    }
 
+    if whole_row {
+        let relation = PgRelation::open(foreigntableid);
+        for attr in relation.tuple_desc().iter() {
+            if attr.attisdropped || ret.iter().any(|c| c.attnum == attr.attnum) {
+                continue;
+            }
+            ret.push(TargetColumn {
+                attnum: attr.attnum,
+                name: remote_column_name(foreigntableid, attr.attnum),
+                type_oid: attr.atttypid,
+                typmod: attr.atttypmod,
+            });
+        }
     }
 
     ret
 
-}
\ No newline at end of file
+}